@@ -0,0 +1,48 @@
+#[cfg(target_os = "linux")]
+fn main() {
+    use std::{sync::Arc, thread, time::Instant};
+    use xcap::{Monitor, SharedCapturer};
+
+    const CAPTURES_PER_THREAD: usize = 20;
+
+    let monitors = Monitor::all().unwrap();
+    let threads = monitors.len().max(4);
+
+    // One connection shared by every thread versus one connection per thread
+    for pool_size in [1, threads] {
+        let capturer = Arc::new(SharedCapturer::new(pool_size).unwrap());
+        let start = Instant::now();
+
+        let handles: Vec<_> = (0..threads)
+            .map(|index| {
+                let capturer = capturer.clone();
+                let monitor = monitors[index % monitors.len()].clone();
+
+                thread::spawn(move || {
+                    for _ in 0..CAPTURES_PER_THREAD {
+                        capturer.capture_monitor(&monitor).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        println!(
+            "pool size {}: {} captures on {} threads in {:?} ({:?} per capture)",
+            pool_size,
+            threads * CAPTURES_PER_THREAD,
+            threads,
+            elapsed,
+            elapsed / (threads * CAPTURES_PER_THREAD) as u32
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("SharedCapturer is only available on Linux");
+}
@@ -0,0 +1,106 @@
+use image::{RgbaImage, imageops};
+
+use crate::{Monitor, Window, XCapError, XCapResult, monitor::validate_region};
+
+/// Source of captures, so code consuming them can run against [`MockBackend`] in tests
+/// instead of a display. Monitors and windows are addressed by their `id()`.
+pub trait CaptureBackend {
+    fn capture_region(
+        &self,
+        monitor_id: u32,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage>;
+
+    fn capture_window(&self, window_id: u32) -> XCapResult<RgbaImage>;
+}
+
+/// Captures through the platform's backend, the same as [`Monitor::capture_region`] and
+/// [`Window::capture_image`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlatformBackend;
+
+impl CaptureBackend for PlatformBackend {
+    fn capture_region(
+        &self,
+        monitor_id: u32,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        for monitor in Monitor::all()? {
+            if monitor.id()? == monitor_id {
+                return monitor.capture_region(x, y, width, height);
+            }
+        }
+
+        Err(XCapError::new("Not found monitor"))
+    }
+
+    fn capture_window(&self, window_id: u32) -> XCapResult<RgbaImage> {
+        for window in Window::all()? {
+            if window.id()? == window_id {
+                return window.capture_image();
+            }
+        }
+
+        Err(XCapError::new("Not found window"))
+    }
+}
+
+/// Serves every capture from a fixed image regardless of the id: regions are cropped out of
+/// it and windows get the whole image.
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    image: RgbaImage,
+}
+
+impl MockBackend {
+    pub fn new(image: RgbaImage) -> MockBackend {
+        MockBackend { image }
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn capture_region(
+        &self,
+        _monitor_id: u32,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        let (image_width, image_height) = self.image.dimensions();
+        validate_region(x, y, width, height, 0, 0, image_width, image_height)?;
+
+        Ok(imageops::crop_imm(&self.image, x, y, width, height).to_image())
+    }
+
+    fn capture_window(&self, _window_id: u32) -> XCapResult<RgbaImage> {
+        Ok(self.image.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_backend() {
+        let mut image = RgbaImage::new(4, 4);
+        image.put_pixel(2, 1, Rgba([1, 2, 3, 255]));
+        let backend: &dyn CaptureBackend = &MockBackend::new(image.clone());
+
+        let region = backend.capture_region(0, 2, 1, 2, 2).unwrap();
+        assert_eq!(region.dimensions(), (2, 2));
+        assert_eq!(region.get_pixel(0, 0), &Rgba([1, 2, 3, 255]));
+
+        assert!(backend.capture_region(0, 3, 0, 2, 1).is_err());
+        assert_eq!(backend.capture_window(0).unwrap(), image);
+    }
+}
@@ -0,0 +1,137 @@
+use image::RgbaImage;
+
+use crate::{Monitor, XCapResult};
+
+const DEFAULT_TILE_SIZE: u32 = 64;
+
+/// Area of a frame that differs from the previous one, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffFrame {
+    pub image: RgbaImage,
+    /// Tiles that changed since the previous capture, the whole frame on the first capture
+    /// or after the monitor size changed.
+    pub changed: Vec<DiffRect>,
+}
+
+/// Captures a monitor repeatedly, reporting which tiles changed between consecutive frames
+/// so encoders only need to send the deltas.
+#[derive(Debug, Clone)]
+pub struct DiffCapturer {
+    monitor: Monitor,
+    tile_size: u32,
+    previous: Option<RgbaImage>,
+}
+
+impl DiffCapturer {
+    pub fn new(monitor: Monitor) -> DiffCapturer {
+        DiffCapturer::with_tile_size(monitor, DEFAULT_TILE_SIZE)
+    }
+
+    pub fn with_tile_size(monitor: Monitor, tile_size: u32) -> DiffCapturer {
+        DiffCapturer {
+            monitor,
+            tile_size: tile_size.max(1),
+            previous: None,
+        }
+    }
+
+    pub fn capture(&mut self) -> XCapResult<DiffFrame> {
+        let image = self.monitor.capture_image()?;
+
+        let changed = match &self.previous {
+            Some(previous) if previous.dimensions() == image.dimensions() => {
+                diff_tiles(previous, &image, self.tile_size)
+            }
+            _ => vec![DiffRect {
+                x: 0,
+                y: 0,
+                width: image.width(),
+                height: image.height(),
+            }],
+        };
+
+        self.previous = Some(image.clone());
+
+        Ok(DiffFrame { image, changed })
+    }
+}
+
+/// Compare two equally sized images tile by tile, each tile row is a single slice comparison.
+fn diff_tiles(previous: &RgbaImage, next: &RgbaImage, tile_size: u32) -> Vec<DiffRect> {
+    let (width, height) = next.dimensions();
+    let stride = width as usize * 4;
+    let previous = previous.as_raw();
+    let next = next.as_raw();
+
+    let mut changed = Vec::new();
+
+    for y in (0..height).step_by(tile_size as usize) {
+        let tile_height = tile_size.min(height - y);
+
+        for x in (0..width).step_by(tile_size as usize) {
+            let tile_width = tile_size.min(width - x);
+
+            let is_changed = (y..y + tile_height).any(|row| {
+                let start = row as usize * stride + x as usize * 4;
+                let end = start + tile_width as usize * 4;
+
+                previous[start..end] != next[start..end]
+            });
+
+            if is_changed {
+                changed.push(DiffRect {
+                    x,
+                    y,
+                    width: tile_width,
+                    height: tile_height,
+                });
+            }
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_tiles() {
+        let previous = RgbaImage::new(5, 3);
+        let mut next = previous.clone();
+
+        assert!(diff_tiles(&previous, &next, 2).is_empty());
+
+        next.put_pixel(4, 2, Rgba([1, 0, 0, 0]));
+        next.put_pixel(1, 0, Rgba([1, 0, 0, 0]));
+
+        assert_eq!(
+            diff_tiles(&previous, &next, 2),
+            [
+                DiffRect {
+                    x: 0,
+                    y: 0,
+                    width: 2,
+                    height: 2
+                },
+                DiffRect {
+                    x: 4,
+                    y: 2,
+                    width: 1,
+                    height: 1
+                },
+            ]
+        );
+    }
+}
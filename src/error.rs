@@ -10,12 +10,66 @@ pub enum XCapError {
     StdSyncPoisonError(String),
     #[error("Invalid capture region: {0}")]
     InvalidCaptureRegion(String),
+    #[error(
+        "Invalid capture region: region {width}x{height} at ({x}, {y}) exceeds bounds {bounds_width}x{bounds_height}"
+    )]
+    RegionOutOfBounds {
+        x: i64,
+        y: i64,
+        width: u32,
+        height: u32,
+        bounds_width: u32,
+        bounds_height: u32,
+    },
+    #[error("Invalid capture region: {width}x{height} is empty")]
+    InvalidRegion { width: u32, height: u32 },
+    #[error("{0}")]
+    ImageConversion(String),
+    #[error("Region {width}x{height} is too large to allocate")]
+    RegionTooLarge { width: u32, height: u32 },
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+    #[error("No monitor found")]
+    NoMonitor,
 
     #[cfg(target_os = "linux")]
-    #[error(transparent)]
+    #[error("Unsupported {0} depth")]
+    UnsupportedDepth(u8),
+    #[cfg(target_os = "linux")]
+    #[error(
+        "Unsupported {0} depth, monochrome and palette formats are not decoded (likely a pixmap rather than a window)"
+    )]
+    UnsupportedMonochromeDepth(u8),
+    #[cfg(target_os = "linux")]
+    #[error("Not found pixmap format")]
+    PixmapFormatNotFound(u8),
+    #[cfg(target_os = "linux")]
+    #[error("Incomplete image data, expected {expected} bytes, got {got}")]
+    IncompleteImageData { expected: usize, got: usize },
+    #[cfg(target_os = "linux")]
+    #[error("Timed out after {0:?} waiting for the X server")]
+    Timeout(std::time::Duration),
+    #[cfg(target_os = "linux")]
+    #[error("Rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: std::time::Duration },
+    #[cfg(target_os = "linux")]
+    #[error("Window {0} was destroyed during the capture")]
+    WindowGone(u32),
+    #[cfg(target_os = "linux")]
+    #[error("Window {0} is minimized or unmapped")]
+    WindowNotVisible(u32),
+    #[cfg(target_os = "linux")]
+    #[error("No active window")]
+    NoActiveWindow,
+    #[cfg(target_os = "linux")]
+    #[error("Invalid window id {id}: {source}")]
+    InvalidWindowId { id: u32, source: xcb::Error },
+    // Not transparent, so the xcb error stays in the `source()` chain instead of being skipped
+    #[cfg(target_os = "linux")]
+    #[error("X11 error: {0}")]
     XcbError(#[from] xcb::Error),
     #[cfg(target_os = "linux")]
-    #[error(transparent)]
+    #[error("X11 connection error: {0}")]
     XcbConnError(#[from] xcb::ConnError),
     #[cfg(target_os = "linux")]
     #[error(transparent)]
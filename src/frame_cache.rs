@@ -0,0 +1,130 @@
+use std::mem;
+
+use image::RgbaImage;
+
+use crate::{Monitor, XCapError, XCapResult};
+
+/// Result of [`FrameCache::capture_region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachedFrame<'a> {
+    /// Byte for byte the same as the previous capture.
+    Unchanged,
+    /// Differs from the previous capture, or is the first one.
+    New(&'a RgbaImage),
+}
+
+/// Captures a monitor region repeatedly and reports identical consecutive frames as
+/// [`CachedFrame::Unchanged`], so idle screens don't have to be re-encoded or diffed.
+///
+/// Frames are decoded into a scratch buffer and compared with the previous one in place,
+/// the two buffers swap roles on every new frame so steady captures don't allocate.
+#[derive(Debug, Clone)]
+pub struct FrameCache {
+    monitor: Monitor,
+    previous: Option<RgbaImage>,
+    scratch: Vec<u8>,
+}
+
+impl FrameCache {
+    pub fn new(monitor: Monitor) -> FrameCache {
+        FrameCache {
+            monitor,
+            previous: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn capture_region(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<CachedFrame<'_>> {
+        #[cfg(target_os = "linux")]
+        let dimensions =
+            self.monitor
+                .capture_region_into(x, y, width, height, &mut self.scratch)?;
+        #[cfg(not(target_os = "linux"))]
+        let dimensions = {
+            let image = self.monitor.capture_region(x, y, width, height)?;
+            let dimensions = image.dimensions();
+            self.scratch = image.into_raw();
+
+            dimensions
+        };
+
+        update(&mut self.previous, &mut self.scratch, dimensions)
+    }
+
+    /// The most recent frame that was reported as new.
+    pub fn last_frame(&self) -> Option<&RgbaImage> {
+        self.previous.as_ref()
+    }
+}
+
+/// Make the pixels in `scratch` the new previous frame unless they match the current one.
+/// The previous frame's buffer is handed back as `scratch` for the next capture.
+fn update<'a>(
+    previous: &'a mut Option<RgbaImage>,
+    scratch: &mut Vec<u8>,
+    dimensions: (u32, u32),
+) -> XCapResult<CachedFrame<'a>> {
+    let is_unchanged = previous.as_ref().is_some_and(|previous| {
+        previous.dimensions() == dimensions && previous.as_raw() == scratch
+    });
+
+    if is_unchanged {
+        return Ok(CachedFrame::Unchanged);
+    }
+
+    let recycled = previous.take().map(RgbaImage::into_raw).unwrap_or_default();
+    let (width, height) = dimensions;
+    let image = RgbaImage::from_raw(width, height, mem::replace(scratch, recycled))
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))?;
+
+    Ok(CachedFrame::New(previous.insert(image)))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let mut previous = None;
+        let image = RgbaImage::new(2, 2);
+
+        let mut scratch = image.as_raw().clone();
+        assert!(matches!(
+            update(&mut previous, &mut scratch, (2, 2)),
+            Ok(CachedFrame::New(_))
+        ));
+        assert!(scratch.is_empty());
+
+        let mut scratch = image.as_raw().clone();
+        assert_eq!(
+            update(&mut previous, &mut scratch, (2, 2)).unwrap(),
+            CachedFrame::Unchanged
+        );
+        assert_eq!(scratch, *image.as_raw());
+
+        let mut changed = image.clone();
+        changed.put_pixel(1, 1, Rgba([1, 0, 0, 0]));
+        let mut scratch = changed.as_raw().clone();
+        assert_eq!(
+            update(&mut previous, &mut scratch, (2, 2)).unwrap(),
+            CachedFrame::New(&changed)
+        );
+        // The replaced frame's buffer is reused for the next capture
+        assert_eq!(scratch, *image.as_raw());
+
+        let mut scratch = RgbaImage::new(2, 1).into_raw();
+        assert!(matches!(
+            update(&mut previous, &mut scratch, (2, 1)),
+            Ok(CachedFrame::New(_))
+        ));
+    }
+}
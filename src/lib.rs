@@ -1,5 +1,11 @@
+mod capture_backend;
+mod diff_capturer;
 mod error;
+mod frame_cache;
 mod monitor;
+mod rgb;
+mod tone_curve;
+mod trim;
 mod video_recorder;
 mod window;
 
@@ -16,10 +22,35 @@ mod platform;
 mod platform;
 
 pub use image;
+#[cfg(target_os = "linux")]
+pub use xcb;
 
+pub use capture_backend::{CaptureBackend, MockBackend, PlatformBackend};
+pub use diff_capturer::{DiffCapturer, DiffFrame, DiffRect};
 pub use error::{XCapError, XCapResult};
-pub use monitor::Monitor;
+pub use frame_cache::{CachedFrame, FrameCache};
+pub use monitor::{CaptureFrame, Monitor, Rounding};
+pub use rgb::{rgba_image_into_rgb, rgba_to_rgb_in_place};
+pub use tone_curve::ToneCurve;
+pub use trim::trim_transparent;
 pub use window::Window;
 
+#[cfg(target_os = "linux")]
+pub use platform::damage_capturer::DamageCapturer;
+#[cfg(target_os = "linux")]
+pub use platform::float::Standardization;
+#[cfg(target_os = "linux")]
+pub use platform::frame_stream::FrameStream;
+#[cfg(target_os = "linux")]
+pub use platform::nv12::{Nv12Image, YuvMatrix};
+#[cfg(target_os = "linux")]
+pub use platform::planar::PlanarImage;
+#[cfg(target_os = "linux")]
+pub use platform::shared_capturer::{RateLimitMode, SharedCapturer};
+#[cfg(target_os = "linux")]
+pub use platform::utils::{Backend, XorgCaptureOptions, current_backend};
+#[cfg(target_os = "linux")]
+pub use platform::xorg_capture::{CaptureTimings, ImageReply, ImageRows, RawImage};
+
 pub use video_recorder::Frame;
 pub use video_recorder::VideoRecorder;
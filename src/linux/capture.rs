@@ -1,28 +1,187 @@
-use image::RgbaImage;
+use std::path::Path;
 
-use crate::error::XCapResult;
+use image::{
+    ColorType, DynamicImage, GrayImage, RgbImage, Rgba32FImage, RgbaImage,
+    imageops::{
+        FilterType, crop_imm, flip_horizontal, flip_vertical, resize, rotate90, rotate180,
+        rotate270,
+    },
+};
+use xcb::{
+    Connection, Xid,
+    randr::{MonitorInfoBuf, Rotation},
+    x::{self, ScreenBuf},
+};
+
+use crate::error::{XCapError, XCapResult};
 
 use super::{
+    float::{Standardization, to_f32},
     impl_monitor::ImplMonitor,
     impl_window::ImplWindow,
-    utils::{get_current_screen_buf, get_monitor_info_buf, wayland_detect},
+    nv12::{Nv12Image, YuvMatrix, to_nv12},
+    planar::{PlanarImage, to_planar},
+    utils::{get_current_screen_buf, get_screen_buf, wayland_detect},
     wayland_capture::wayland_capture,
-    xorg_capture::xorg_capture,
+    wayland_window_capture::wayland_capture_window,
+    xorg_capture::{
+        CaptureConnection, CaptureTimings, ImageReply, OutputFormat, RawImage, convert_rgba,
+        fit_factor, xorg_capture, xorg_capture_alpha, xorg_capture_composite, xorg_capture_dynamic,
+        xorg_capture_f32, xorg_capture_gray, xorg_capture_nv12, xorg_capture_planar,
+        xorg_capture_raw, xorg_capture_raw_planes, xorg_capture_regions, xorg_capture_reply,
+        xorg_capture_scaled, xorg_capture_timed, xorg_capture_to_mmap, xorg_capture_window,
+        xorg_capture_with, xorg_capture_with_connection, xorg_composite_cursor, xorg_pixel_color,
+    },
 };
 
-pub fn capture_monitor(impl_monitor: &ImplMonitor) -> XCapResult<RgbaImage> {
-    let monitor_info_buf = get_monitor_info_buf(impl_monitor.output)?;
+/// Validate a monitor relative region against the monitor's size and offset it by the
+/// monitor's position, giving desktop coordinates. The X root window and the Wayland capture
+/// functions are both addressed in desktop coordinates.
+fn desktop_region(
+    (monitor_x, monitor_y, monitor_width, monitor_height): (i16, i16, u16, u16),
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(i64, i64)> {
+    if x as u64 + width as u64 > monitor_width as u64
+        || y as u64 + height as u64 > monitor_height as u64
+    {
+        return Err(XCapError::RegionOutOfBounds {
+            x: x as i64,
+            y: y as i64,
+            width,
+            height,
+            bounds_width: monitor_width as u32,
+            bounds_height: monitor_height as u32,
+        });
+    }
 
-    if wayland_detect() {
-        wayland_capture(
-            monitor_info_buf.x() as i32,
-            monitor_info_buf.y() as i32,
-            monitor_info_buf.width() as i32,
-            monitor_info_buf.height() as i32,
-        )
+    Ok((monitor_x as i64 + x as i64, monitor_y as i64 + y as i64))
+}
+
+fn monitor_bounds(monitor_info_buf: &MonitorInfoBuf) -> (i16, i16, u16, u16) {
+    (
+        monitor_info_buf.x(),
+        monitor_info_buf.y(),
+        monitor_info_buf.width(),
+        monitor_info_buf.height(),
+    )
+}
+
+/// Capture a monitor relative region on Wayland, at the same place on the desktop the X11
+/// backend reads it from.
+fn wayland_capture_monitor_region(
+    monitor_info_buf: &MonitorInfoBuf,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    let (x, y) = desktop_region(monitor_bounds(monitor_info_buf), x, y, width, height)?;
+
+    // In range, the region fits in a monitor whose position and size are 16 bit
+    wayland_capture(x as i32, y as i32, width as i32, height as i32)
+}
+
+/// Validate a monitor relative region and translate it to root window coordinates,
+/// so `GetImage` is never sent a rectangle that would fail with BadMatch.
+pub fn get_root_region(
+    monitor_info_buf: &MonitorInfoBuf,
+    screen_buf: &ScreenBuf,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(i32, i32)> {
+    let (root_x, root_y) = desktop_region(monitor_bounds(monitor_info_buf), x, y, width, height)?;
+    let root_width = screen_buf.width_in_pixels() as i64;
+    let root_height = screen_buf.height_in_pixels() as i64;
+
+    if root_x < 0
+        || root_y < 0
+        || root_x + width as i64 > root_width
+        || root_y + height as i64 > root_height
+    {
+        return Err(XCapError::RegionOutOfBounds {
+            x: root_x,
+            y: root_y,
+            width,
+            height,
+            bounds_width: root_width as u32,
+            bounds_height: root_height as u32,
+        });
+    }
+
+    Ok((root_x as i32, root_y as i32))
+}
+
+/// Whether the Wayland backends failed because the compositor offers no way to capture at all,
+/// rather than because a capture went wrong.
+fn is_wayland_unsupported(err: &XCapError) -> bool {
+    matches!(
+        err,
+        XCapError::LibwayshotError(
+            libwayshot_xcap::Error::ProtocolNotFound(_)
+                | libwayshot_xcap::Error::Connect(_)
+                | libwayshot_xcap::Error::Bind(_)
+        ) | XCapError::Unsupported(_)
+    )
+}
+
+/// Try the Wayland backends first, XWayland and hybrid sessions may still serve the frame
+/// from the X root window when none of them is available.
+fn wayland_or_xorg_capture<W, X>(wayland: W, xorg: X) -> XCapResult<RgbaImage>
+where
+    W: FnOnce() -> XCapResult<RgbaImage>,
+    X: FnOnce() -> XCapResult<RgbaImage>,
+{
+    match wayland() {
+        Err(err) if is_wayland_unsupported(&err) => {
+            log::info!("wayland capture unsupported ({}), falling back to X11", err);
+            xorg()
+        }
+        Ok(rgba_image) => {
+            log::trace!("frame captured by the wayland backend");
+            Ok(rgba_image)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Apply the CRTC's RandR rotation, counter-clockwise, and then its reflection, turning a
+/// capture of the root window, which is already in the orientation the user sees, into the
+/// image in the panel's scanout orientation.
+fn orient(rgba_image: RgbaImage, rotation: Rotation) -> RgbaImage {
+    let rgba_image = if rotation.contains(Rotation::ROTATE_90) {
+        rotate270(&rgba_image)
+    } else if rotation.contains(Rotation::ROTATE_180) {
+        rotate180(&rgba_image)
+    } else if rotation.contains(Rotation::ROTATE_270) {
+        rotate90(&rgba_image)
+    } else {
+        rgba_image
+    };
+    let rgba_image = if rotation.contains(Rotation::REFLECT_X) {
+        flip_horizontal(&rgba_image)
     } else {
-        let screen_buf = get_current_screen_buf()?;
+        rgba_image
+    };
 
+    if rotation.contains(Rotation::REFLECT_Y) {
+        flip_vertical(&rgba_image)
+    } else {
+        rgba_image
+    }
+}
+
+pub fn capture_monitor(impl_monitor: &ImplMonitor) -> XCapResult<RgbaImage> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    let xorg = || {
+        let screen_buf = impl_monitor.screen_buf()?;
+
+        log::trace!("capturing with the X11 backend");
         xorg_capture(
             screen_buf.root(),
             monitor_info_buf.x() as i32,
@@ -30,9 +189,107 @@ pub fn capture_monitor(impl_monitor: &ImplMonitor) -> XCapResult<RgbaImage> {
             monitor_info_buf.width() as u32,
             monitor_info_buf.height() as u32,
         )
+    };
+
+    if wayland_detect() {
+        wayland_or_xorg_capture(
+            || {
+                wayland_capture(
+                    monitor_info_buf.x() as i32,
+                    monitor_info_buf.y() as i32,
+                    monitor_info_buf.width() as i32,
+                    monitor_info_buf.height() as i32,
+                )
+            },
+            xorg,
+        )
+    } else {
+        xorg()
     }
 }
 
+/// The monitor in its panel's scanout orientation, RandR applies the CRTC transform at
+/// scanout so the root window itself is in the orientation the user sees. X11 only.
+pub fn capture_monitor_scanout(impl_monitor: &ImplMonitor) -> XCapResult<RgbaImage> {
+    if wayland_detect() {
+        return Err(XCapError::new(
+            "Scanout orientation capture is not supported on Wayland",
+        ));
+    }
+
+    let rgba_image = capture_monitor(impl_monitor)?;
+
+    Ok(orient(rgba_image, impl_monitor.crtc_rotation()?))
+}
+
+/// The whole X screen in a single `GetImage` of the root window, regardless of monitor layout.
+pub fn capture_root() -> XCapResult<RgbaImage> {
+    capture_screen_root(get_current_screen_buf()?)
+}
+
+/// Root window of another screen of a multi-screen (`:0.N`) server. Wayland sessions only
+/// have a single Xwayland screen.
+pub fn capture_screen(screen: usize) -> XCapResult<RgbaImage> {
+    capture_screen_root(get_screen_buf(screen)?)
+}
+
+fn capture_screen_root(screen_buf: ScreenBuf) -> XCapResult<RgbaImage> {
+    let width = screen_buf.width_in_pixels() as u32;
+    let height = screen_buf.height_in_pixels() as u32;
+
+    let xorg = || xorg_capture(screen_buf.root(), 0, 0, width, height);
+
+    if wayland_detect() {
+        wayland_or_xorg_capture(|| wayland_capture(0, 0, width as i32, height as i32), xorg)
+    } else {
+        xorg()
+    }
+}
+
+pub fn capture_monitor_with_connection(
+    impl_monitor: &ImplMonitor,
+    conn: &Connection,
+) -> XCapResult<RgbaImage> {
+    if wayland_detect() {
+        return Err(XCapError::new(
+            "Capturing over an X connection is not supported on Wayland",
+        ));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+
+    xorg_capture_with_connection(
+        conn,
+        screen_buf.root(),
+        monitor_info_buf.x() as i32,
+        monitor_info_buf.y() as i32,
+        monitor_info_buf.width() as u32,
+        monitor_info_buf.height() as u32,
+    )
+}
+
+pub fn capture_monitor_raw(
+    impl_monitor: &ImplMonitor,
+    plane_mask: Option<u32>,
+) -> XCapResult<RawImage> {
+    if wayland_detect() {
+        return Err(XCapError::new("Raw capture is not supported on Wayland"));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+
+    xorg_capture_raw_planes(
+        screen_buf.root(),
+        monitor_info_buf.x() as i32,
+        monitor_info_buf.y() as i32,
+        monitor_info_buf.width() as u32,
+        monitor_info_buf.height() as u32,
+        plane_mask.unwrap_or(u32::MAX),
+    )
+}
+
 pub fn capture_region(
     impl_monitor: &ImplMonitor,
     x: u32,
@@ -40,26 +297,686 @@ pub fn capture_region(
     width: u32,
     height: u32,
 ) -> XCapResult<RgbaImage> {
-    let monitor_info_buf = get_monitor_info_buf(impl_monitor.output)?;
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    let xorg = || {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        log::trace!("capturing with the X11 backend");
+        xorg_capture(screen_buf.root(), root_x, root_y, width, height)
+    };
 
     if wayland_detect() {
-        wayland_capture(x as i32, y as i32, width as i32, height as i32)
+        wayland_or_xorg_capture(
+            || wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height),
+            xorg,
+        )
     } else {
-        let screen_buf = get_current_screen_buf()?;
+        xorg()
+    }
+}
 
-        xorg_capture(
+pub fn capture_region_dynamic(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<DynamicImage> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        // Screenshots from the Wayland backends are always opaque
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+        let (width, height) = rgba_image.dimensions();
+
+        let mut rgb = Vec::new();
+        convert_rgba(rgba_image.as_raw(), OutputFormat::Rgb, &mut rgb);
+
+        RgbImage::from_raw(width, height, rgb)
+            .map(DynamicImage::ImageRgb8)
+            .ok_or_else(|| XCapError::ImageConversion("RgbImage::from_raw failed".to_string()))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_dynamic(screen_buf.root(), root_x, root_y, width, height)
+    }
+}
+
+pub fn capture_region_scaled(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    divisor: u32,
+) -> XCapResult<RgbaImage> {
+    if divisor == 0 {
+        return Err(XCapError::new("Scale divisor must be greater than 0"));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+
+        Ok(resize(
+            &rgba_image,
+            width / divisor,
+            height / divisor,
+            FilterType::Nearest,
+        ))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_scaled(screen_buf.root(), root_x, root_y, width, height, divisor)
+    }
+}
+
+pub fn capture_region_with_cursor(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    if wayland_detect() {
+        return Err(XCapError::new("Cursor capture is not supported on Wayland"));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+    let (root_x, root_y) = get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+    let mut rgba_image = xorg_capture(screen_buf.root(), root_x, root_y, width, height)?;
+    xorg_composite_cursor(root_x, root_y, &mut rgba_image)?;
+
+    Ok(rgba_image)
+}
+
+pub fn capture_pixel_color(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+) -> XCapResult<(u8, u8, u8, u8)> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, 1, 1)?;
+        let [r, g, b, a] = rgba_image
+            .get_pixel_checked(0, 0)
+            .ok_or(XCapError::new("Wayland capture returned an empty image"))?
+            .0;
+
+        Ok((r, g, b, a))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) = get_root_region(&monitor_info_buf, &screen_buf, x, y, 1, 1)?;
+
+        xorg_pixel_color(screen_buf.root(), root_x, root_y)
+    }
+}
+
+pub fn capture_region_with(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    output: OutputFormat,
+    buf: &mut Vec<u8>,
+) -> XCapResult<(u32, u32)> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+
+        convert_rgba(rgba_image.as_raw(), output, buf);
+
+        Ok(rgba_image.dimensions())
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_with(
+            screen_buf.root(),
+            root_x,
+            root_y,
+            width,
+            height,
+            output,
+            buf,
+        )
+    }
+}
+
+pub fn capture_regions(
+    impl_monitor: &ImplMonitor,
+    regions: &[(u32, u32, u32, u32)],
+) -> XCapResult<Vec<RgbaImage>> {
+    if wayland_detect() {
+        return regions
+            .iter()
+            .map(|&(x, y, width, height)| capture_region(impl_monitor, x, y, width, height))
+            .collect();
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+    let root_regions = regions
+        .iter()
+        .map(|&(x, y, width, height)| {
+            let (root_x, root_y) =
+                get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+            Ok((root_x, root_y, width, height))
+        })
+        .collect::<XCapResult<Vec<_>>>()?;
+
+    xorg_capture_regions(screen_buf.root(), &root_regions)
+}
+
+/// Capture every monitor, on X11 with all requests sent before the first reply is read.
+pub fn capture_monitors(impl_monitors: &[ImplMonitor]) -> XCapResult<Vec<RgbaImage>> {
+    if wayland_detect() {
+        return impl_monitors.iter().map(capture_monitor).collect();
+    }
+
+    // Monitors are placed on their screen's root, pipeline the monitors of each screen together
+    let mut rgba_images = Vec::with_capacity(impl_monitors.len());
+    for screen_monitors in impl_monitors.chunk_by(|a, b| a.screen == b.screen) {
+        let screen_buf = screen_monitors[0].screen_buf()?;
+        let regions = screen_monitors
+            .iter()
+            .map(|impl_monitor| {
+                let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+                Ok((
+                    monitor_info_buf.x() as i32,
+                    monitor_info_buf.y() as i32,
+                    monitor_info_buf.width() as u32,
+                    monitor_info_buf.height() as u32,
+                ))
+            })
+            .collect::<XCapResult<Vec<_>>>()?;
+
+        rgba_images.extend(xorg_capture_regions(screen_buf.root(), &regions)?);
+    }
+
+    Ok(rgba_images)
+}
+
+pub fn capture_region_timed(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(RgbaImage, CaptureTimings)> {
+    if wayland_detect() {
+        return Err(XCapError::new("Timed capture is not supported on Wayland"));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+    let (root_x, root_y) = get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+    xorg_capture_timed(screen_buf.root(), root_x, root_y, width, height)
+}
+
+pub fn capture_region_to_mmap(
+    impl_monitor: &ImplMonitor,
+    path: &Path,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(u32, u32, ColorType)> {
+    if wayland_detect() {
+        return Err(XCapError::new(
+            "Capturing into a mapped file is not supported on Wayland",
+        ));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+    let (root_x, root_y) = get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+    xorg_capture_to_mmap(screen_buf.root(), path, root_x, root_y, width, height)
+}
+
+pub fn capture_region_reply(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<ImageReply> {
+    if wayland_detect() {
+        return Err(XCapError::new("Reply capture is not supported on Wayland"));
+    }
+
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+    let screen_buf = impl_monitor.screen_buf()?;
+    let (root_x, root_y) = get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+    xorg_capture_reply(screen_buf.root(), root_x, root_y, width, height)
+}
+
+pub fn capture_region_gray(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<GrayImage> {
+    let mut luma = Vec::new();
+    let (width, height) = capture_region_with(
+        impl_monitor,
+        x,
+        y,
+        width,
+        height,
+        OutputFormat::Gray,
+        &mut luma,
+    )?;
+
+    GrayImage::from_raw(width, height, luma)
+        .ok_or_else(|| XCapError::ImageConversion("GrayImage::from_raw failed".to_string()))
+}
+
+pub fn capture_region_premultiplied(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    let mut rgba = Vec::new();
+    let (width, height) = capture_region_with(
+        impl_monitor,
+        x,
+        y,
+        width,
+        height,
+        OutputFormat::RgbaPremultiplied,
+        &mut rgba,
+    )?;
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+pub fn capture_region_nv12(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    matrix: YuvMatrix,
+) -> XCapResult<Nv12Image> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+        let (width, height) = rgba_image.dimensions();
+
+        Ok(to_nv12(width, height, matrix, |x, y| {
+            let [r, g, b, a] = rgba_image.get_pixel(x, y).0;
+            (r, g, b, a)
+        }))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_nv12(screen_buf.root(), root_x, root_y, width, height, matrix)
+    }
+}
+
+pub fn capture_region_f32(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    standardization: Option<Standardization>,
+) -> XCapResult<Rgba32FImage> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+        let (width, height) = rgba_image.dimensions();
+
+        Ok(to_f32(width, height, standardization, |x, y| {
+            let [r, g, b, a] = rgba_image.get_pixel(x, y).0;
+            (r, g, b, a)
+        }))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_f32(
             screen_buf.root(),
-            monitor_info_buf.x() as i32 + x as i32,
-            monitor_info_buf.y() as i32 + y as i32,
+            root_x,
+            root_y,
             width,
             height,
+            standardization,
         )
     }
 }
 
+pub fn capture_region_planar(
+    impl_monitor: &ImplMonitor,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<PlanarImage> {
+    let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+
+    if wayland_detect() {
+        let rgba_image = wayland_capture_monitor_region(&monitor_info_buf, x, y, width, height)?;
+        let (width, height) = rgba_image.dimensions();
+
+        Ok(to_planar(width, height, |x, y| {
+            let [r, g, b, a] = rgba_image.get_pixel(x, y).0;
+            (r, g, b, a)
+        }))
+    } else {
+        let screen_buf = impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        xorg_capture_planar(screen_buf.root(), root_x, root_y, width, height)
+    }
+}
+
+/// The window can be destroyed between listing it and `GetImage`, the server then answers
+/// BadDrawable/BadWindow. Report that as [`XCapError::WindowGone`] so callers can tell it
+/// apart from real failures. BadMatch is left as is, live windows partly off-screen or not
+/// viewable answer it too.
+fn window_gone(impl_window: &ImplWindow, err: XCapError) -> XCapError {
+    match err {
+        XCapError::XcbError(xcb::Error::Protocol(xcb::ProtocolError::X(
+            x::Error::Drawable(_) | x::Error::Window(_),
+            _,
+        ))) => XCapError::WindowGone(impl_window.window.resource_id()),
+        err => err,
+    }
+}
+
+/// BadMatch from `GetImage`, the window exists but its contents can't be read, e.g. because
+/// part of it is off-screen.
+fn is_unreadable(err: &XCapError) -> bool {
+    matches!(
+        err,
+        XCapError::XcbError(xcb::Error::Protocol(xcb::ProtocolError::X(
+            x::Error::Match(_),
+            _
+        )))
+    )
+}
+
+/// Capture the whole window with `capture`, sizing it from one geometry query.
+fn capture_xorg_window<T, F>(impl_window: &ImplWindow, capture: F) -> XCapResult<T>
+where
+    F: FnOnce(u32, u32) -> XCapResult<T>,
+{
+    impl_window
+        .size()
+        .and_then(|(width, height)| capture(width, height))
+        .map_err(|err| window_gone(impl_window, err))
+}
+
 pub fn capture_window(impl_window: &ImplWindow) -> XCapResult<RgbaImage> {
-    let width = impl_window.width()?;
-    let height = impl_window.height()?;
+    let xorg =
+        || xorg_capture_window(impl_window.window).map_err(|err| window_gone(impl_window, err));
+
+    if wayland_detect() {
+        wayland_or_xorg_capture(
+            || wayland_capture_window(&impl_window.title()?, &impl_window.app_name()?),
+            xorg,
+        )
+    } else {
+        xorg()
+    }
+}
+
+/// Capture the window, reporting whether its alpha channel holds anything but opaque pixels.
+/// Wayland captures carry no visual, so there the pixels are always scanned.
+pub fn capture_window_alpha(impl_window: &ImplWindow) -> XCapResult<(RgbaImage, bool)> {
+    if wayland_detect() {
+        let rgba_image = capture_window(impl_window)?;
+        let alpha_significant = rgba_image.pixels().any(|pixel| pixel[3] != 255);
+
+        return Ok((rgba_image, alpha_significant));
+    }
+
+    capture_xorg_window(impl_window, |width, height| {
+        xorg_capture_alpha(impl_window.window, 0, 0, width, height)
+    })
+}
+
+/// Capture every window on one connection, each downscaled by the smallest divisor that fits
+/// it in `max_dim` x `max_dim`. Unmapped, minimized and empty windows are left out, and so
+/// are windows too thin to keep a pixel once scaled, destroyed before their turn came or
+/// whose contents can't be read.
+pub fn capture_window_thumbnails(
+    impl_windows: Vec<ImplWindow>,
+    max_dim: u32,
+) -> XCapResult<Vec<(ImplWindow, RgbaImage)>> {
+    let mut connection = CaptureConnection::new()?;
+    let mut thumbnails = Vec::with_capacity(impl_windows.len());
+
+    for impl_window in impl_windows {
+        let thumbnail = impl_window
+            .is_visible()
+            .map_err(|err| window_gone(&impl_window, err))
+            .and_then(|is_visible| {
+                if !is_visible {
+                    return Ok(None);
+                }
+
+                capture_xorg_window(&impl_window, |width, height| {
+                    if width == 0 || height == 0 {
+                        return Ok(None);
+                    }
+
+                    // A window far thinner than it is long would scale to no rows or columns
+                    let divisor = fit_factor(width, height, (max_dim, max_dim));
+                    if width / divisor == 0 || height / divisor == 0 {
+                        return Ok(None);
+                    }
+
+                    connection
+                        .capture_scaled(impl_window.window, 0, 0, width, height, divisor)
+                        .map(Some)
+                })
+            });
+
+        match thumbnail {
+            Ok(Some(thumbnail)) => thumbnails.push((impl_window, thumbnail)),
+            Ok(None) | Err(XCapError::WindowGone(_)) => {}
+            Err(err) if is_unreadable(&err) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(thumbnails)
+}
+
+/// Raise the window as far as the window manager allows before capturing from it, so the
+/// capture doesn't show the other windows' contents without Composite. With `restore` the
+/// window goes back to where it was stacked afterwards, even when the capture failed.
+pub fn capture_window_raised(impl_window: &ImplWindow, restore: bool) -> XCapResult<RgbaImage> {
+    if wayland_detect() {
+        return Err(XCapError::new(
+            "Raising windows is not supported on Wayland",
+        ));
+    }
+
+    let above = impl_window
+        .raise()
+        .map_err(|err| window_gone(impl_window, err))?;
+
+    let result = capture_window(impl_window);
+
+    // Best effort, a window manager may not let clients restack its frames
+    if let (true, Some(above)) = (restore, above)
+        && let Err(err) = impl_window.restack_below(above)
+    {
+        log::debug!("restoring the window's stacking failed {}", err);
+    }
+
+    result
+}
 
-    xorg_capture(impl_window.window, 0, 0, width, height)
+/// Capture the window manager's frame around the window, decorations included. Wayland
+/// compositors only hand out the client surface, so there it is the same as `capture_window`.
+pub fn capture_window_frame(impl_window: &ImplWindow) -> XCapResult<RgbaImage> {
+    if wayland_detect() {
+        return capture_window(impl_window);
+    }
+
+    let frame = impl_window
+        .frame()
+        .map_err(|err| window_gone(impl_window, err))?;
+
+    capture_window(&frame)
+}
+
+/// Capture the window's own contents even where it is covered, falling back to a direct
+/// capture when Composite is unavailable or the window has no backing pixmap (unmapped).
+pub fn capture_window_composite(impl_window: &ImplWindow) -> XCapResult<RgbaImage> {
+    capture_xorg_window(impl_window, |width, height| {
+        xorg_capture_composite(impl_window.window, width, height).or_else(|err| {
+            log::debug!("composite capture failed ({}), capturing directly", err);
+            xorg_capture(impl_window.window, 0, 0, width, height)
+        })
+    })
+}
+
+pub fn capture_window_with_connection(
+    impl_window: &ImplWindow,
+    conn: &Connection,
+) -> XCapResult<RgbaImage> {
+    capture_xorg_window(impl_window, |width, height| {
+        xorg_capture_with_connection(conn, impl_window.window, 0, 0, width, height)
+    })
+}
+
+/// Reject a window relative region reaching past the window's `window_width` x
+/// `window_height`.
+fn check_window_region(
+    (x, y, width, height): (u32, u32, u32, u32),
+    window_width: u32,
+    window_height: u32,
+) -> XCapResult<()> {
+    if x as u64 + width as u64 > window_width as u64
+        || y as u64 + height as u64 > window_height as u64
+    {
+        return Err(XCapError::InvalidCaptureRegion(format!(
+            "Region ({}, {}, {}, {}) is outside window bounds (0, 0, {}, {})",
+            x, y, width, height, window_width, window_height
+        )));
+    }
+
+    Ok(())
+}
+
+/// Capture a sub-rectangle of the window. On Wayland the whole window is captured the way
+/// `capture_window` does, compositor first, and the region is cropped out of that image.
+pub fn capture_window_region(
+    impl_window: &ImplWindow,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    if width == 0 || height == 0 {
+        return Err(XCapError::InvalidRegion { width, height });
+    }
+
+    let region = (x, y, width, height);
+
+    if wayland_detect() {
+        let rgba_image = capture_window(impl_window)?;
+        check_window_region(region, rgba_image.width(), rgba_image.height())?;
+
+        return Ok(crop_imm(&rgba_image, x, y, width, height).to_image());
+    }
+
+    capture_xorg_window(impl_window, |window_width, window_height| {
+        check_window_region(region, window_width, window_height)?;
+
+        xorg_capture(impl_window.window, x as i32, y as i32, width, height)
+    })
+}
+
+pub fn capture_window_gray(impl_window: &ImplWindow) -> XCapResult<GrayImage> {
+    capture_xorg_window(impl_window, |width, height| {
+        xorg_capture_gray(impl_window.window, 0, 0, width, height)
+    })
+}
+
+pub fn capture_window_raw(impl_window: &ImplWindow) -> XCapResult<RawImage> {
+    capture_xorg_window(impl_window, |width, height| {
+        xorg_capture_raw(impl_window.window, 0, 0, width, height)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_region() {
+        // Regions are relative to the monitor, both backends get them offset to the desktop
+        let monitor = (1920, -100, 1280, 1024);
+
+        assert_eq!(
+            desktop_region(monitor, 0, 0, 1280, 1024).unwrap(),
+            (1920, -100)
+        );
+        assert_eq!(
+            desktop_region(monitor, 10, 20, 100, 50).unwrap(),
+            (1930, -80)
+        );
+
+        assert!(matches!(
+            desktop_region(monitor, 1200, 0, 100, 1),
+            Err(XCapError::RegionOutOfBounds {
+                x: 1200,
+                bounds_width: 1280,
+                ..
+            })
+        ));
+        assert!(desktop_region(monitor, u32::MAX, 0, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_orient() {
+        // 2x1: red, green
+        let rgba_image = RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 255, 0, 255]).unwrap();
+
+        assert_eq!(orient(rgba_image.clone(), Rotation::ROTATE_0), rgba_image);
+
+        // Counter-clockwise, red ends up at the bottom
+        let rotated = orient(rgba_image.clone(), Rotation::ROTATE_90);
+        assert_eq!(rotated.dimensions(), (1, 2));
+        assert_eq!(rotated.get_pixel(0, 1).0, [255, 0, 0, 255]);
+
+        let reflected = orient(rgba_image, Rotation::ROTATE_0 | Rotation::REFLECT_X);
+        assert_eq!(reflected.get_pixel(0, 0).0, [0, 255, 0, 255]);
+    }
 }
@@ -0,0 +1,248 @@
+use image::RgbaImage;
+use xcb::{
+    Xid, damage,
+    x::{self, Rectangle},
+    xfixes,
+};
+
+use crate::{
+    DiffRect, Window,
+    error::{XCapError, XCapResult},
+};
+
+use super::{
+    utils::{get_current_screen_buf, wayland_detect},
+    xorg_capture::CaptureConnection,
+};
+
+/// Captures a window, or the whole root window, only once the X server reported that
+/// part of it changed, using the DAMAGE extension.
+///
+/// [`DamageCapturer::next_damage`] sleeps in the X event queue until a change comes in
+/// instead of polling `GetImage`, so an idle screen costs no CPU. It is X11 only, Wayland
+/// compositors don't offer damage reports to other clients.
+pub struct DamageCapturer {
+    connection: CaptureConnection,
+    window: x::Window,
+    damage: damage::Damage,
+}
+
+/// Smallest rectangle covering both `a` and `b`.
+fn union_rect(a: Rectangle, b: Rectangle) -> Rectangle {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    let right = (a.x as i32 + a.width as i32).max(b.x as i32 + b.width as i32);
+    let bottom = (a.y as i32 + a.height as i32).max(b.y as i32 + b.height as i32);
+
+    Rectangle {
+        x,
+        y,
+        width: (right - x as i32) as u16,
+        height: (bottom - y as i32) as u16,
+    }
+}
+
+/// Part of `area` inside a `width` x `height` window, `None` when nothing of it is.
+fn clip_rect(area: Rectangle, width: u32, height: u32) -> Option<DiffRect> {
+    let left = (area.x as i64).max(0);
+    let top = (area.y as i64).max(0);
+    let right = (area.x as i64 + area.width as i64).min(width as i64);
+    let bottom = (area.y as i64 + area.height as i64).min(height as i64);
+
+    (left < right && top < bottom).then(|| DiffRect {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+    })
+}
+
+impl DamageCapturer {
+    /// Watch the root window, i.e. everything shown on the current screen.
+    pub fn new() -> XCapResult<DamageCapturer> {
+        DamageCapturer::watch(get_current_screen_buf()?.root())
+    }
+
+    /// Watch `window` alone, rectangles are relative to its top left corner.
+    pub fn for_window(window: &Window) -> XCapResult<DamageCapturer> {
+        DamageCapturer::watch(window.impl_window.window)
+    }
+
+    fn watch(window: x::Window) -> XCapResult<DamageCapturer> {
+        if wayland_detect() {
+            return Err(XCapError::Unsupported(
+                "DAMAGE extension is not available on Wayland".to_string(),
+            ));
+        }
+
+        let connection = CaptureConnection::new()?;
+        let conn = connection.connection();
+
+        // DAMAGE requests are rejected until the client announced its version
+        let has_damage = conn
+            .active_extensions()
+            .any(|item| item == xcb::Extension::Damage)
+            && conn
+                .wait_for_reply(conn.send_request(&damage::QueryVersion {
+                    client_major_version: 1,
+                    client_minor_version: 1,
+                }))
+                .is_ok();
+
+        if !has_damage {
+            return Err(XCapError::Unsupported(
+                "DAMAGE extension is not available".to_string(),
+            ));
+        }
+
+        let damage = conn.generate_id();
+        conn.send_and_check_request(&damage::Create {
+            damage,
+            drawable: x::Drawable::Window(window),
+            level: damage::ReportLevel::BoundingBox,
+        })
+        .map_err(xcb::Error::from)?;
+
+        Ok(DamageCapturer {
+            connection,
+            window,
+            damage,
+        })
+    }
+
+    /// Block until part of the window changed, then capture the bounding box of every
+    /// change reported so far, clipped to the window.
+    pub fn next_damage(&mut self) -> XCapResult<(DiffRect, RgbaImage)> {
+        loop {
+            let area = self.wait_for_damage()?;
+
+            // Damage can reach past the window's edges, e.g. onto its border
+            let (width, height) = self.connection.window_size(self.window)?;
+            let Some(rect) = clip_rect(area, width, height) else {
+                continue;
+            };
+
+            let image = self.connection.capture(
+                self.window,
+                rect.x as i32,
+                rect.y as i32,
+                rect.width,
+                rect.height,
+            )?;
+
+            return Ok((rect, image));
+        }
+    }
+
+    /// Wait for the next damage report and fold in the ones already queued, clearing the
+    /// damage so the server reports later changes to the same pixels again.
+    fn wait_for_damage(&self) -> XCapResult<Rectangle> {
+        let conn = self.connection.connection();
+
+        let mut area = loop {
+            if let xcb::Event::Damage(damage::Event::Notify(event)) = conn.wait_for_event()?
+                && event.damage() == self.damage
+            {
+                break event.area();
+            }
+        };
+
+        // Fold in the reports already queued, one capture covers them all
+        while let Some(event) = conn.poll_for_queued_event().map_err(xcb::Error::from)? {
+            if let xcb::Event::Damage(damage::Event::Notify(event)) = event
+                && event.damage() == self.damage
+            {
+                area = union_rect(area, event.area());
+            }
+        }
+
+        // Without clearing the damage the server reports nothing new for the same pixels
+        conn.send_and_check_request(&damage::Subtract {
+            damage: self.damage,
+            repair: xfixes::Region::none(),
+            parts: xfixes::Region::none(),
+        })
+        .map_err(xcb::Error::from)?;
+
+        Ok(area)
+    }
+}
+
+impl Drop for DamageCapturer {
+    fn drop(&mut self) {
+        let conn = self.connection.connection();
+        conn.send_request(&damage::Destroy {
+            damage: self.damage,
+        });
+        let _ = conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i16, y: i16, width: u16, height: u16) -> Rectangle {
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_union_rect() {
+        let union = union_rect(rect(10, 10, 5, 5), rect(0, 12, 4, 10));
+        assert_eq!(
+            (union.x, union.y, union.width, union.height),
+            (0, 10, 15, 12)
+        );
+
+        let inner = union_rect(rect(0, 0, 100, 100), rect(20, 30, 10, 10));
+        assert_eq!(
+            (inner.x, inner.y, inner.width, inner.height),
+            (0, 0, 100, 100)
+        );
+
+        let negative = union_rect(rect(-5, -8, 10, 10), rect(4, 0, 6, 6));
+        assert_eq!(
+            (negative.x, negative.y, negative.width, negative.height),
+            (-5, -8, 15, 14)
+        );
+    }
+
+    #[test]
+    fn test_clip_rect() {
+        let inside = DiffRect {
+            x: 2,
+            y: 3,
+            width: 4,
+            height: 5,
+        };
+        assert_eq!(clip_rect(rect(2, 3, 4, 5), 100, 100), Some(inside));
+
+        // Past the top left edge, the origin and the size shrink together
+        assert_eq!(
+            clip_rect(rect(-5, -8, 10, 10), 100, 100),
+            Some(DiffRect {
+                x: 0,
+                y: 0,
+                width: 5,
+                height: 2,
+            })
+        );
+        assert_eq!(
+            clip_rect(rect(95, 98, 10, 10), 100, 100),
+            Some(DiffRect {
+                x: 95,
+                y: 98,
+                width: 5,
+                height: 2,
+            })
+        );
+
+        assert_eq!(clip_rect(rect(-10, 0, 10, 10), 100, 100), None);
+        assert_eq!(clip_rect(rect(100, 0, 10, 10), 100, 100), None);
+    }
+}
@@ -0,0 +1,66 @@
+use image::Rgba32FImage;
+
+/// Per channel `(value - mean) / std` applied to the red, green and blue channels after
+/// scaling them to `0.0..=1.0`, e.g. the ImageNet statistics many vision models expect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Standardization {
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+}
+
+/// Build an `f32` RGBA frame reading every source pixel once through `read_pixel`. Channels
+/// are scaled to `0.0..=1.0`, and standardized with `standardization` except alpha.
+pub(crate) fn to_f32<F>(
+    width: u32,
+    height: u32,
+    standardization: Option<Standardization>,
+    read_pixel: F,
+) -> Rgba32FImage
+where
+    F: Fn(u32, u32) -> (u8, u8, u8, u8),
+{
+    // Only 256 inputs per channel, look the results up instead of dividing per pixel
+    let mut tables = [[0.0; 256]; 4];
+    for (channel, table) in tables.iter_mut().enumerate() {
+        let (mean, std) = match standardization {
+            Some(standardization) if channel < 3 => {
+                (standardization.mean[channel], standardization.std[channel])
+            }
+            _ => (0.0, 1.0),
+        };
+
+        for (value, entry) in table.iter_mut().enumerate() {
+            *entry = (value as f32 / 255.0 - mean) / std;
+        }
+    }
+
+    Rgba32FImage::from_fn(width, height, |x, y| {
+        let (r, g, b, a) = read_pixel(x, y);
+
+        image::Rgba([
+            tables[0][r as usize],
+            tables[1][g as usize],
+            tables[2][b as usize],
+            tables[3][a as usize],
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_f32() {
+        let image = to_f32(2, 1, None, |x, _| (x as u8 * 255, 51, 0, 255));
+        assert_eq!(image.get_pixel(0, 0).0, [0.0, 0.2, 0.0, 1.0]);
+        assert_eq!(image.get_pixel(1, 0).0, [1.0, 0.2, 0.0, 1.0]);
+
+        let standardization = Standardization {
+            mean: [0.5, 0.5, 0.5],
+            std: [0.5, 0.25, 0.5],
+        };
+        let image = to_f32(1, 1, Some(standardization), |_, _| (255, 255, 0, 0));
+        assert_eq!(image.get_pixel(0, 0).0, [1.0, 2.0, -1.0, 0.0]);
+    }
+}
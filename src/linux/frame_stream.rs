@@ -0,0 +1,104 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::RgbaImage;
+use xcb::x::Window;
+
+use crate::error::{XCapError, XCapResult};
+
+use super::{
+    capture::capture_monitor, impl_monitor::ImplMonitor, utils::wayland_detect,
+    xorg_capture::CaptureConnection,
+};
+
+/// Region of the root window covered by the monitor, resolved once when the stream starts.
+struct XorgTarget {
+    capture_connection: CaptureConnection,
+    root: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+/// Iterator over monitor frames paced at a fixed rate, see [`crate::Monitor::frames`].
+///
+/// On X11 the stream owns its own connection, which is closed when the stream is dropped.
+pub struct FrameStream {
+    impl_monitor: ImplMonitor,
+    interval: Duration,
+    next_frame_at: Option<Instant>,
+    xorg_target: Option<XorgTarget>,
+}
+
+impl FrameStream {
+    pub(crate) fn new(impl_monitor: ImplMonitor, fps: u32) -> XCapResult<FrameStream> {
+        if fps == 0 {
+            return Err(XCapError::new("Frame rate must be greater than 0"));
+        }
+
+        let xorg_target = if wayland_detect() {
+            None
+        } else {
+            let monitor_info_buf = impl_monitor.monitor_info_buf()?;
+            let screen_buf = impl_monitor.screen_buf()?;
+
+            Some(XorgTarget {
+                capture_connection: CaptureConnection::new()?,
+                root: screen_buf.root(),
+                x: monitor_info_buf.x() as i32,
+                y: monitor_info_buf.y() as i32,
+                width: monitor_info_buf.width() as u32,
+                height: monitor_info_buf.height() as u32,
+            })
+        };
+
+        Ok(FrameStream {
+            impl_monitor,
+            interval: Duration::from_secs(1) / fps,
+            next_frame_at: None,
+            xorg_target,
+        })
+    }
+
+    fn capture(&mut self) -> XCapResult<RgbaImage> {
+        let Some(xorg_target) = self.xorg_target.as_mut() else {
+            return capture_monitor(&self.impl_monitor);
+        };
+
+        if xorg_target.capture_connection.is_broken() {
+            xorg_target.capture_connection = CaptureConnection::new()?;
+        }
+
+        xorg_target.capture_connection.capture(
+            xorg_target.root,
+            xorg_target.x,
+            xorg_target.y,
+            xorg_target.width,
+            xorg_target.height,
+        )
+    }
+}
+
+impl Iterator for FrameStream {
+    type Item = XCapResult<RgbaImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = Instant::now();
+
+        if let Some(wait) = self
+            .next_frame_at
+            .and_then(|item| item.checked_duration_since(now))
+        {
+            thread::sleep(wait);
+        }
+
+        // A slow consumer drops frames instead of bursting to catch up
+        let frame_at = self.next_frame_at.map_or(now, |item| item.max(now));
+        self.next_frame_at = Some(frame_at + self.interval);
+
+        Some(self.capture())
+    }
+}
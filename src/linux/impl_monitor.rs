@@ -1,32 +1,50 @@
-use std::{ffi::CStr, sync::mpsc::Receiver};
+use std::{ffi::CStr, os::fd::AsRawFd, path::Path, sync::mpsc::Receiver};
 
-use image::RgbaImage;
+use image::{ColorType, DynamicImage, GrayImage, Rgba32FImage, RgbaImage};
 use xcb::{
-    Xid,
+    Connection, Xid,
     randr::{
-        GetCrtcInfo, GetMonitors, GetOutputInfo, GetOutputProperty, GetScreenResources, Mode,
-        ModeFlag, ModeInfo, Output, Rotation,
+        GetCrtcInfo, GetCrtcInfoReply, GetMonitors, GetOutputInfo, GetOutputProperty,
+        GetScreenResources, Mode, ModeFlag, ModeInfo, MonitorInfoBuf, Output, Rotation,
+    },
+    x::{
+        ATOM_ANY, ATOM_RESOURCE_MANAGER, ATOM_STRING, CURRENT_TIME, GetProperty, QueryPointer,
+        ScreenBuf,
     },
-    x::{ATOM_ANY, ATOM_RESOURCE_MANAGER, ATOM_STRING, CURRENT_TIME, GetProperty},
 };
 
 use crate::{
     error::{XCapError, XCapResult},
+    monitor::validate_region,
     video_recorder::Frame,
 };
 
 use super::{
-    capture::{capture_monitor, capture_region},
+    capture::{
+        capture_monitor, capture_monitor_raw, capture_monitor_scanout,
+        capture_monitor_with_connection, capture_monitors, capture_pixel_color, capture_region,
+        capture_region_dynamic, capture_region_f32, capture_region_gray, capture_region_nv12,
+        capture_region_planar, capture_region_premultiplied, capture_region_reply,
+        capture_region_scaled, capture_region_timed, capture_region_to_mmap, capture_region_with,
+        capture_region_with_cursor, capture_regions, capture_root, capture_screen,
+    },
+    float::Standardization,
+    frame_stream::FrameStream,
     impl_video_recorder::ImplVideoRecorder,
+    nv12::{Nv12Image, YuvMatrix},
+    planar::PlanarImage,
     utils::{
-        get_atom, get_current_screen_buf, get_monitor_info_buf, get_xcb_connection_and_index,
-        wayland_detect,
+        get_atom, get_current_screen_buf, get_monitor_info_buf, get_screen_buf,
+        get_xcb_connection_and_index, is_tcp_socket, wayland_detect,
     },
+    xorg_capture::{CaptureTimings, ImageReply, OutputFormat, RawImage, check_depth},
 };
 
 #[derive(Debug, Clone)]
 pub(crate) struct ImplMonitor {
     pub output: Output,
+    /// Index of the X screen the output belongs to, see [`ImplMonitor::screen_buf`]
+    pub screen: usize,
 }
 
 // per https://gitlab.freedesktop.org/xorg/app/xrandr/-/blob/master/xrandr.c#L576
@@ -84,7 +102,8 @@ fn get_scale_factor() -> XCapResult<f32> {
         property: ATOM_RESOURCE_MANAGER,
         r#type: ATOM_STRING,
         long_offset: 0,
-        long_length: 60,
+        // The whole resource database, `Xft.dpi` isn't necessarily among the first entries
+        long_length: u32::MAX,
     });
 
     let get_property_reply = conn.wait_for_reply(get_property_cookie)?;
@@ -103,7 +122,7 @@ fn get_scale_factor() -> XCapResult<f32> {
     Ok(dpi / 96.0)
 }
 
-fn get_rotation_frequency(mode_infos: Vec<ModeInfo>, output: &Output) -> XCapResult<(f32, f32)> {
+fn get_crtc_info(output: &Output) -> XCapResult<GetCrtcInfoReply> {
     let (conn, _) = get_xcb_connection_and_index()?;
     let get_output_info_cookie = conn.send_request(&GetOutputInfo {
         output: *output,
@@ -117,16 +136,24 @@ fn get_rotation_frequency(mode_infos: Vec<ModeInfo>, output: &Output) -> XCapRes
         config_timestamp: CURRENT_TIME,
     });
 
-    let get_crtc_info_reply = conn.wait_for_reply(get_crtc_info_cookie)?;
+    Ok(conn.wait_for_reply(get_crtc_info_cookie)?)
+}
+
+fn get_rotation_frequency(mode_infos: Vec<ModeInfo>, output: &Output) -> XCapResult<(f32, f32)> {
+    let get_crtc_info_reply = get_crtc_info(output)?;
 
     let mode = get_crtc_info_reply.mode();
 
-    let rotation = match get_crtc_info_reply.rotation() {
-        Rotation::ROTATE_0 => 0.0,
-        Rotation::ROTATE_90 => 90.0,
-        Rotation::ROTATE_180 => 180.0,
-        Rotation::ROTATE_270 => 270.0,
-        _ => 0.0,
+    // Reflection bits are set alongside the rotation, only the rotation is reported here
+    let rotation = get_crtc_info_reply.rotation();
+    let rotation = if rotation.contains(Rotation::ROTATE_90) {
+        90.0
+    } else if rotation.contains(Rotation::ROTATE_180) {
+        180.0
+    } else if rotation.contains(Rotation::ROTATE_270) {
+        270.0
+    } else {
+        0.0
     };
 
     let frequency = get_current_frequency(mode_infos, mode);
@@ -134,11 +161,9 @@ fn get_rotation_frequency(mode_infos: Vec<ModeInfo>, output: &Output) -> XCapRes
     Ok((rotation, frequency))
 }
 
-fn get_mode_infos() -> XCapResult<Vec<ModeInfo>> {
+fn get_mode_infos(screen_buf: &ScreenBuf) -> XCapResult<Vec<ModeInfo>> {
     let (conn, _) = get_xcb_connection_and_index()?;
 
-    let screen_buf = get_current_screen_buf()?;
-
     let get_screen_resources_cookie = conn.send_request(&GetScreenResources {
         window: screen_buf.root(),
     });
@@ -195,29 +220,40 @@ fn is_builtin_edid(edid: &[u8]) -> bool {
 }
 
 impl ImplMonitor {
-    fn new(output: Output) -> ImplMonitor {
-        ImplMonitor { output }
+    fn new(output: Output, screen: usize) -> ImplMonitor {
+        ImplMonitor { output, screen }
+    }
+
+    /// Screen whose root window the monitor's coordinates are relative to.
+    pub(crate) fn screen_buf(&self) -> XCapResult<ScreenBuf> {
+        get_screen_buf(self.screen)
+    }
+
+    pub(crate) fn monitor_info_buf(&self) -> XCapResult<MonitorInfoBuf> {
+        get_monitor_info_buf(self.output, &self.screen_buf()?)
     }
 
     pub fn all() -> XCapResult<Vec<ImplMonitor>> {
         let (conn, _) = get_xcb_connection_and_index()?;
 
-        let screen_buf = get_current_screen_buf()?;
-
-        let get_monitors_cookie = conn.send_request(&GetMonitors {
-            window: screen_buf.root(),
-            get_active: true,
-        });
+        let setup = conn.get_setup();
 
-        let get_monitors_reply = conn.wait_for_reply(get_monitors_cookie)?;
+        let mut impl_monitors = Vec::new();
 
-        let monitor_info_iterator = get_monitors_reply.monitors();
+        // Multi-screen servers (`:0.0`, `:0.1`, ...) have a root window per screen, each with
+        // its own monitors
+        for (screen, screen_buf) in setup.roots().enumerate() {
+            let get_monitors_cookie = conn.send_request(&GetMonitors {
+                window: screen_buf.root(),
+                get_active: true,
+            });
 
-        let mut impl_monitors = Vec::new();
+            let get_monitors_reply = conn.wait_for_reply(get_monitors_cookie)?;
 
-        for monitor_info in monitor_info_iterator {
-            for &output in monitor_info.outputs() {
-                impl_monitors.push(ImplMonitor::new(output));
+            for monitor_info in get_monitors_reply.monitors() {
+                for &output in monitor_info.outputs() {
+                    impl_monitors.push(ImplMonitor::new(output, screen));
+                }
             }
         }
 
@@ -225,7 +261,7 @@ impl ImplMonitor {
     }
 
     pub fn from_point(x: i32, y: i32) -> XCapResult<ImplMonitor> {
-        let (conn, _) = get_xcb_connection_and_index()?;
+        let (conn, index) = get_xcb_connection_and_index()?;
 
         let screen_buf = get_current_screen_buf()?;
 
@@ -251,13 +287,62 @@ impl ImplMonitor {
 
             if x >= left && x < right && y >= top && y < bottom {
                 if let Some(&output) = monitor_info.outputs().first() {
-                    return Ok(ImplMonitor::new(output));
+                    return Ok(ImplMonitor::new(output, *index as usize));
                 }
             }
         }
 
         Err(XCapError::new("Not found monitor"))
     }
+
+    pub fn cursor_position() -> XCapResult<(i32, i32)> {
+        let (conn, _) = get_xcb_connection_and_index()?;
+
+        let screen_buf = get_current_screen_buf()?;
+
+        let query_pointer_cookie = conn.send_request(&QueryPointer {
+            window: screen_buf.root(),
+        });
+        let query_pointer_reply = conn.wait_for_reply(query_pointer_cookie)?;
+
+        if !query_pointer_reply.same_screen() {
+            return Err(XCapError::new("Pointer is on another screen"));
+        }
+
+        // Same logical coordinates as `x()` and `y()`, so they can go back into `from_point`
+        let scale_factor = get_scale_factor().unwrap_or(1.0);
+        let x = (query_pointer_reply.root_x() as f32 / scale_factor) as i32;
+        let y = (query_pointer_reply.root_y() as f32 / scale_factor) as i32;
+
+        Ok((x, y))
+    }
+
+    pub fn from_cursor() -> XCapResult<ImplMonitor> {
+        let (x, y) = ImplMonitor::cursor_position()?;
+
+        ImplMonitor::from_point(x, y)
+    }
+
+    pub fn is_remote_display() -> XCapResult<bool> {
+        // Portal captures are handed over locally, whatever the X server they run beside
+        if wayland_detect() {
+            return Ok(false);
+        }
+
+        let (conn, _) = get_xcb_connection_and_index()?;
+
+        Ok(is_tcp_socket(conn.as_raw_fd())?)
+    }
+
+    pub fn from_name(name: &str) -> XCapResult<ImplMonitor> {
+        for impl_monitor in ImplMonitor::all()? {
+            if impl_monitor.name()? == name {
+                return Ok(impl_monitor);
+            }
+        }
+
+        Err(XCapError::new(format!("Not found monitor {}", name)))
+    }
 }
 
 impl ImplMonitor {
@@ -278,35 +363,35 @@ impl ImplMonitor {
     }
 
     pub fn x(&self) -> XCapResult<i32> {
-        let x = get_monitor_info_buf(self.output)?.x();
+        let x = self.monitor_info_buf()?.x();
         let scale_factor = self.scale_factor()?;
 
         Ok(((x as f32) / scale_factor) as i32)
     }
 
     pub fn y(&self) -> XCapResult<i32> {
-        let y = get_monitor_info_buf(self.output)?.y();
+        let y = self.monitor_info_buf()?.y();
         let scale_factor = self.scale_factor()?;
 
         Ok(((y as f32) / scale_factor) as i32)
     }
 
     pub fn width(&self) -> XCapResult<u32> {
-        let width = get_monitor_info_buf(self.output)?.width();
+        let width = self.monitor_info_buf()?.width();
         let scale_factor = self.scale_factor()?;
 
         Ok(((width as f32) / scale_factor) as u32)
     }
 
     pub fn height(&self) -> XCapResult<u32> {
-        let height = get_monitor_info_buf(self.output)?.height();
+        let height = self.monitor_info_buf()?.height();
         let scale_factor = self.scale_factor()?;
 
         Ok(((height as f32) / scale_factor) as u32)
     }
 
     pub fn rotation(&self) -> XCapResult<f32> {
-        let mode_infos = get_mode_infos()?;
+        let mode_infos = get_mode_infos(&self.screen_buf()?)?;
         let (rotation, _) = get_rotation_frequency(mode_infos, &self.output).unwrap_or((0.0, 0.0));
 
         Ok(rotation)
@@ -318,14 +403,26 @@ impl ImplMonitor {
         Ok(scale_factor)
     }
 
+    pub fn dpi(&self) -> XCapResult<f32> {
+        let monitor_info_buf = self.monitor_info_buf()?;
+        let width_in_millimeters = monitor_info_buf.width_in_millimeters();
+
+        // Projectors and some virtual outputs report no physical size
+        if width_in_millimeters == 0 {
+            return Err(XCapError::new("Monitor has no physical size"));
+        }
+
+        Ok(monitor_info_buf.width() as f32 * 25.4 / width_in_millimeters as f32)
+    }
+
     pub fn frequency(&self) -> XCapResult<f32> {
-        let mode_infos = get_mode_infos()?;
+        let mode_infos = get_mode_infos(&self.screen_buf()?)?;
         let (_, frequency) = get_rotation_frequency(mode_infos, &self.output).unwrap_or((0.0, 0.0));
         Ok(frequency)
     }
 
     pub fn is_primary(&self) -> XCapResult<bool> {
-        let primary = get_monitor_info_buf(self.output)?.primary();
+        let primary = self.monitor_info_buf()?.primary();
 
         Ok(primary)
     }
@@ -346,26 +443,301 @@ impl ImplMonitor {
         capture_monitor(self)
     }
 
-    pub fn capture_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<RgbaImage> {
+    pub fn capture_image_scanout(&self) -> XCapResult<RgbaImage> {
+        capture_monitor_scanout(self)
+    }
+
+    /// RandR rotation and reflection of the CRTC driving this monitor.
+    pub fn crtc_rotation(&self) -> XCapResult<Rotation> {
+        Ok(get_crtc_info(&self.output)?.rotation())
+    }
+
+    fn check_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<()> {
         // Validate region bounds
         let monitor_x = self.x()?;
         let monitor_y = self.y()?;
         let monitor_width = self.width()?;
         let monitor_height = self.height()?;
 
-        if width > monitor_width
-            || height > monitor_height
-            || x as u32 + width > monitor_width
-            || y as u32 + height > monitor_height
-        {
-            return Err(XCapError::InvalidCaptureRegion(format!(
-                "Region ({}, {}, {}, {}) is outside monitor bounds ({}, {}, {}, {})",
-                x, y, width, height, monitor_x, monitor_y, monitor_width, monitor_height
-            )));
+        validate_region(
+            x,
+            y,
+            width,
+            height,
+            monitor_x,
+            monitor_y,
+            monitor_width,
+            monitor_height,
+        )?;
+
+        if !wayland_detect() {
+            check_depth(self.screen_buf()?.root_depth())?;
         }
+
+        Ok(())
+    }
+
+    pub fn pixel_color(&self, x: u32, y: u32) -> XCapResult<(u8, u8, u8, u8)> {
+        self.check_region(x, y, 1, 1)?;
+
+        capture_pixel_color(self, x, y)
+    }
+
+    pub fn frames(&self, fps: u32) -> XCapResult<FrameStream> {
+        FrameStream::new(self.clone(), fps)
+    }
+
+    pub fn refresh_rate(&self) -> XCapResult<f32> {
+        let mode_infos = get_mode_infos(&self.screen_buf()?)?;
+        let (_, frequency) = get_rotation_frequency(mode_infos, &self.output)?;
+
+        // Disabled outputs have no CRTC mode to derive a rate from
+        if frequency <= 0.0 {
+            return Err(XCapError::new("Monitor has no active mode"));
+        }
+
+        Ok(frequency)
+    }
+
+    pub fn frames_at_refresh_rate(&self) -> XCapResult<FrameStream> {
+        let fps = self.refresh_rate()?.round() as u32;
+
+        FrameStream::new(self.clone(), fps.max(1))
+    }
+
+    pub fn capture_root() -> XCapResult<RgbaImage> {
+        capture_root()
+    }
+
+    pub fn capture_screen(screen: usize) -> XCapResult<RgbaImage> {
+        capture_screen(screen)
+    }
+
+    pub fn screen(&self) -> XCapResult<usize> {
+        Ok(self.screen)
+    }
+
+    pub fn capture_image_with_connection(&self, conn: &Connection) -> XCapResult<RgbaImage> {
+        capture_monitor_with_connection(self, conn)
+    }
+
+    pub fn capture_raw(&self, plane_mask: Option<u32>) -> XCapResult<RawImage> {
+        capture_monitor_raw(self, plane_mask)
+    }
+
+    pub fn capture_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<RgbaImage> {
+        self.check_region(x, y, width, height)?;
+
         capture_region(self, x, y, width, height)
     }
 
+    pub fn capture_region_into(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut Vec<u8>,
+    ) -> XCapResult<(u32, u32)> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_with(self, x, y, width, height, OutputFormat::Rgba, buf)
+    }
+
+    pub fn capture_region_rgb_into(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut Vec<u8>,
+    ) -> XCapResult<(u32, u32)> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_with(self, x, y, width, height, OutputFormat::Rgb, buf)
+    }
+
+    pub fn capture_region_bgra(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<Vec<u8>> {
+        self.check_region(x, y, width, height)?;
+
+        let mut bgra = Vec::new();
+        capture_region_with(self, x, y, width, height, OutputFormat::Bgra, &mut bgra)?;
+
+        Ok(bgra)
+    }
+
+    pub fn capture_region_bgr(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<Vec<u8>> {
+        self.check_region(x, y, width, height)?;
+
+        let mut bgr = Vec::new();
+        capture_region_with(self, x, y, width, height, OutputFormat::Bgr, &mut bgr)?;
+
+        Ok(bgr)
+    }
+
+    pub fn capture_region_dynamic(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<DynamicImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_dynamic(self, x, y, width, height)
+    }
+
+    pub fn capture_region_scaled(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        divisor: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_scaled(self, x, y, width, height, divisor)
+    }
+
+    pub fn capture_region_with_cursor(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_with_cursor(self, x, y, width, height)
+    }
+
+    pub fn capture_region_nv12(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        matrix: YuvMatrix,
+    ) -> XCapResult<Nv12Image> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_nv12(self, x, y, width, height, matrix)
+    }
+
+    pub fn capture_region_f32(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        standardization: Option<Standardization>,
+    ) -> XCapResult<Rgba32FImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_f32(self, x, y, width, height, standardization)
+    }
+
+    pub fn capture_region_planar(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<PlanarImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_planar(self, x, y, width, height)
+    }
+
+    pub fn capture_region_to_mmap(
+        &self,
+        path: &Path,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(u32, u32, ColorType)> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_to_mmap(self, path, x, y, width, height)
+    }
+
+    pub fn capture_all() -> XCapResult<Vec<(ImplMonitor, RgbaImage)>> {
+        let impl_monitors = ImplMonitor::all()?;
+        let rgba_images = capture_monitors(&impl_monitors)?;
+
+        Ok(impl_monitors.into_iter().zip(rgba_images).collect())
+    }
+
+    pub fn capture_regions(&self, regions: &[(u32, u32, u32, u32)]) -> XCapResult<Vec<RgbaImage>> {
+        for &(x, y, width, height) in regions {
+            self.check_region(x, y, width, height)?;
+        }
+
+        capture_regions(self, regions)
+    }
+
+    pub fn capture_region_timed(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(RgbaImage, CaptureTimings)> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_timed(self, x, y, width, height)
+    }
+
+    pub fn capture_region_reply(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<ImageReply> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_reply(self, x, y, width, height)
+    }
+
+    pub fn capture_region_gray(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<GrayImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_gray(self, x, y, width, height)
+    }
+
+    pub fn capture_region_premultiplied(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.check_region(x, y, width, height)?;
+
+        capture_region_premultiplied(self, x, y, width, height)
+    }
+
     pub fn video_recorder(&self) -> XCapResult<(ImplVideoRecorder, Receiver<Frame>)> {
         ImplVideoRecorder::new(self.clone())
     }
@@ -1,21 +1,35 @@
-use image::RgbaImage;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::{GrayImage, RgbaImage};
 use xcb::{
-    Xid,
+    Connection, Xid, XidNew,
     x::{
-        ATOM_ATOM, ATOM_CARDINAL, ATOM_NONE, ATOM_STRING, ATOM_WM_CLASS, ATOM_WM_NAME, Atom,
-        Drawable, GetGeometry, GetProperty, GetPropertyReply, QueryPointer, TranslateCoordinates,
-        Window,
+        self, ATOM_ANY, ATOM_ATOM, ATOM_CARDINAL, ATOM_NONE, ATOM_STRING, ATOM_WM_CLASS,
+        ATOM_WM_NAME, Atom, ChangeWindowAttributes, ConfigWindow, ConfigureWindow, Cw, Drawable,
+        EventMask, GetGeometry, GetProperty, GetPropertyReply, GetWindowAttributes, MapState,
+        QueryPointer, QueryTree, StackMode, TranslateCoordinates, Window,
     },
 };
 
 use crate::error::{XCapError, XCapResult};
 
 use super::{
-    capture::capture_window,
+    capture::{
+        capture_window, capture_window_alpha, capture_window_composite, capture_window_frame,
+        capture_window_gray, capture_window_raised, capture_window_raw, capture_window_region,
+        capture_window_thumbnails, capture_window_with_connection,
+    },
     impl_monitor::ImplMonitor,
-    utils::{get_atom, get_xcb_connection_and_index},
+    utils::{XorgCaptureOptions, connect_with_retry, get_atom, get_xcb_connection_and_index},
+    xorg_capture::RawImage,
 };
 
+/// How long [`ImplWindow::raise`] waits for the window manager to handle the request
+const RAISE_TIMEOUT: Duration = Duration::from_millis(500);
+
 #[derive(Debug, Clone)]
 pub(crate) struct ImplWindow {
     pub window: Window,
@@ -44,6 +58,18 @@ fn get_window_property(
     Ok(window_property_reply)
 }
 
+/// Text of a string property. `STRING` is Latin-1 by definition, and only decoding it as
+/// such keeps titles with accented characters from failing the UTF-8 check.
+fn get_property_text(reply: &GetPropertyReply) -> XCapResult<String> {
+    let value = reply.value::<u8>();
+
+    if reply.r#type() == ATOM_STRING {
+        return Ok(value.iter().map(|&byte| byte as char).collect());
+    }
+
+    Ok(String::from_utf8(value.to_vec())?)
+}
+
 pub fn get_window_pid(window: &Window) -> XCapResult<u32> {
     let wm_pid_atom = get_atom("_NET_WM_PID")?;
 
@@ -70,7 +96,7 @@ fn get_active_window_id() -> XCapResult<u32> {
         }
     }
 
-    Err(XCapError::new("Get active window id failed"))
+    Err(XCapError::NoActiveWindow)
 }
 
 fn get_position_and_size(window: &Window) -> XCapResult<(i32, i32, u32, u32)> {
@@ -96,6 +122,62 @@ fn get_position_and_size(window: &Window) -> XCapResult<(i32, i32, u32, u32)> {
     ))
 }
 
+/// Reparenting window managers put the client inside a frame window holding the decorations,
+/// the top-level window is the ancestor whose parent is the root.
+fn get_frame_window(window: &Window) -> XCapResult<Window> {
+    let (conn, _) = get_xcb_connection_and_index()?;
+    let mut window = *window;
+
+    loop {
+        let query_tree_cookie = conn.send_request(&QueryTree { window });
+        let query_tree_reply = conn.wait_for_reply(query_tree_cookie)?;
+        let parent = query_tree_reply.parent();
+
+        if parent.is_none() || parent == query_tree_reply.root() {
+            return Ok(window);
+        }
+
+        window = parent;
+    }
+}
+
+/// The lowest viewable window stacked above the top-level `frame`, `None` when nothing
+/// covers it.
+fn get_viewable_above(frame: Window) -> XCapResult<Option<Window>> {
+    let (conn, _) = get_xcb_connection_and_index()?;
+
+    let root = conn
+        .wait_for_reply(conn.send_request(&QueryTree { window: frame }))?
+        .root();
+    let query_tree_reply = conn.wait_for_reply(conn.send_request(&QueryTree { window: root }))?;
+
+    // Children are listed bottom to top
+    let above: Vec<Window> = query_tree_reply
+        .children()
+        .iter()
+        .skip_while(|&&child| child != frame)
+        .skip(1)
+        .copied()
+        .collect();
+
+    let cookies: Vec<_> = above
+        .iter()
+        .map(|&window| conn.send_request(&GetWindowAttributes { window }))
+        .collect();
+
+    for (window, cookie) in above.into_iter().zip(cookies) {
+        // A window destroyed in the meantime doesn't cover anything
+        if conn
+            .wait_for_reply(cookie)
+            .is_ok_and(|reply| reply.map_state() == MapState::Viewable)
+        {
+            return Ok(Some(window));
+        }
+    }
+
+    Ok(None)
+}
+
 fn get_window_state(window: &Window) -> XCapResult<(bool, bool)> {
     // https://specifications.freedesktop.org/wm-spec/1.3/ar01s05.html
     let wm_state_atom = get_atom("_NET_WM_STATE")?;
@@ -122,6 +204,19 @@ fn get_window_state(window: &Window) -> XCapResult<(bool, bool)> {
     ))
 }
 
+/// ICCCM `WM_STATE`, `IconicState` is how window managers without EWMH mark minimized windows.
+fn is_iconic(window: &Window) -> XCapResult<bool> {
+    // Without a window manager the atom may not even exist
+    let Ok(wm_state_atom) = get_atom("WM_STATE") else {
+        return Ok(false);
+    };
+
+    let wm_state_reply = get_window_property(*window, wm_state_atom, wm_state_atom, 0, 2)?;
+    let iconic_state = 3;
+
+    Ok(wm_state_reply.value::<u32>().first() == Some(&iconic_state))
+}
+
 impl ImplWindow {
     fn new(window: Window) -> ImplWindow {
         ImplWindow { window }
@@ -168,6 +263,30 @@ impl ImplWindow {
 
         Ok(impl_windows)
     }
+
+    pub fn from_xid(id: u32) -> XCapResult<ImplWindow> {
+        let (conn, _) = get_xcb_connection_and_index()?;
+        let window = Window::new(id);
+
+        // BadWindow when the id was never valid or the window is already destroyed
+        let get_window_attributes_cookie = conn.send_request(&GetWindowAttributes { window });
+        conn.wait_for_reply(get_window_attributes_cookie)
+            .map_err(|source| XCapError::InvalidWindowId { id, source })?;
+
+        Ok(ImplWindow::new(window))
+    }
+
+    pub fn all_with_thumbnails(max_dim: u32) -> XCapResult<Vec<(ImplWindow, RgbaImage)>> {
+        capture_window_thumbnails(ImplWindow::all()?, max_dim)
+    }
+
+    pub fn active() -> XCapResult<ImplWindow> {
+        // The window manager sets the property to None while no window has the focus
+        match get_active_window_id()? {
+            0 => Err(XCapError::NoActiveWindow),
+            id => ImplWindow::from_xid(id),
+        }
+    }
 }
 
 impl ImplWindow {
@@ -183,14 +302,16 @@ impl ImplWindow {
         let get_class_reply =
             get_window_property(self.window, ATOM_WM_CLASS, ATOM_STRING, 0, 1024)?;
 
-        let wm_class = String::from_utf8(get_class_reply.value().to_vec())?;
+        let wm_class = get_property_text(&get_class_reply)?;
 
         // WM_CLASS contains two strings: instance name and class name
-        // We want the class name (second string)
-        let app_name = wm_class
-            .split('\u{0}')
-            .nth(1) // Take the second string (class name)
-            .unwrap_or("")
+        // We want the class name (second string), the instance name when the class is unset
+        let mut names = wm_class.split('\u{0}');
+        let instance_name = names.next().unwrap_or("");
+        let app_name = names
+            .next()
+            .filter(|class_name| !class_name.is_empty())
+            .unwrap_or(instance_name)
             .to_string();
 
         Ok(app_name)
@@ -204,17 +325,17 @@ impl ImplWindow {
             get_window_property(self.window, net_wm_name_atom, utf8_string_atom, 0, 1024)?;
         let title = String::from_utf8(get_title_reply.value().to_vec())?;
 
-        // If _NET_WM_NAME is empty, fall back to WM_NAME with COMPOUND_TEXT type
+        // If _NET_WM_NAME is empty, fall back to WM_NAME, which clients set as STRING or
+        // COMPOUND_TEXT, so any type is accepted
         if title.is_empty() {
-            let compound_text_atom = get_atom("COMPOUND_TEXT")?;
             let get_title_reply =
-                get_window_property(self.window, ATOM_WM_NAME, compound_text_atom, 0, 1024)?;
-            let title = String::from_utf8(get_title_reply.value().to_vec())?;
+                get_window_property(self.window, ATOM_WM_NAME, ATOM_ANY, 0, 1024)?;
+            let title = get_property_text(&get_title_reply)?;
 
             // If both are empty, try to get the parent window
             if title.is_empty() {
                 let (conn, _) = get_xcb_connection_and_index()?;
-                let query_tree_cookie = conn.send_request(&xcb::x::QueryTree {
+                let query_tree_cookie = conn.send_request(&QueryTree {
                     window: self.window,
                 });
                 if let Ok(query_tree_reply) = conn.wait_for_reply(query_tree_cookie) {
@@ -307,6 +428,13 @@ impl ImplWindow {
         Ok(height)
     }
 
+    /// Width and height from a single geometry query, so both belong to the same state.
+    pub fn size(&self) -> XCapResult<(u32, u32)> {
+        let (_, _, width, height) = get_position_and_size(&self.window)?;
+
+        Ok((width, height))
+    }
+
     pub fn is_minimized(&self) -> XCapResult<bool> {
         let (is_minimized, _) = get_window_state(&self.window)?;
 
@@ -314,9 +442,27 @@ impl ImplWindow {
     }
 
     pub fn is_maximized(&self) -> XCapResult<bool> {
-        let (is_minimized, _) = get_window_state(&self.window)?;
+        let (_, is_maximized) = get_window_state(&self.window)?;
 
-        Ok(is_minimized)
+        Ok(is_maximized)
+    }
+
+    pub fn is_visible(&self) -> XCapResult<bool> {
+        let (conn, _) = get_xcb_connection_and_index()?;
+        let get_window_attributes_cookie = conn.send_request(&GetWindowAttributes {
+            window: self.window,
+        });
+        let get_window_attributes_reply = conn.wait_for_reply(get_window_attributes_cookie)?;
+
+        // Viewable means the window and all its ancestors, e.g. the WM frame, are mapped
+        if get_window_attributes_reply.map_state() != MapState::Viewable {
+            return Ok(false);
+        }
+
+        // Some window managers keep minimized windows mapped, so check their state as well
+        let is_hidden = get_window_state(&self.window).is_ok_and(|(is_minimized, _)| is_minimized);
+
+        Ok(!is_hidden && !is_iconic(&self.window)?)
     }
 
     pub fn is_focused(&self) -> XCapResult<bool> {
@@ -328,4 +474,120 @@ impl ImplWindow {
     pub fn capture_image(&self) -> XCapResult<RgbaImage> {
         capture_window(self)
     }
+
+    pub fn capture_image_with_alpha_info(&self) -> XCapResult<(RgbaImage, bool)> {
+        capture_window_alpha(self)
+    }
+
+    pub fn capture_image_if_visible(&self) -> XCapResult<RgbaImage> {
+        if !self.is_visible()? {
+            return Err(XCapError::WindowNotVisible(self.window.resource_id()));
+        }
+
+        capture_window(self)
+    }
+
+    pub fn capture_image_with_connection(&self, conn: &Connection) -> XCapResult<RgbaImage> {
+        capture_window_with_connection(self, conn)
+    }
+
+    pub fn capture_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<RgbaImage> {
+        capture_window_region(self, x, y, width, height)
+    }
+
+    pub fn capture_image_gray(&self) -> XCapResult<GrayImage> {
+        capture_window_gray(self)
+    }
+
+    pub fn capture_image_composite(&self) -> XCapResult<RgbaImage> {
+        capture_window_composite(self)
+    }
+
+    /// Top-level window the window manager drew around this one, itself when not reparented.
+    pub fn frame(&self) -> XCapResult<ImplWindow> {
+        Ok(ImplWindow {
+            window: get_frame_window(&self.window)?,
+        })
+    }
+
+    /// Ask for the window to be raised to the top and wait until the window manager handled
+    /// the request, returning the viewable window that was right above its frame before,
+    /// `None` when nothing covered it. Docks, panels and always-on-top windows stay above it
+    /// by design, so how high the window gets is up to the window manager.
+    pub(crate) fn raise(&self) -> XCapResult<Option<Window>> {
+        let frame = get_frame_window(&self.window)?;
+
+        let Some(above) = get_viewable_above(frame)? else {
+            return Ok(None);
+        };
+
+        // Own connection, the stacking events mustn't pile up in the shared one's queue
+        let (conn, _) =
+            connect_with_retry(&XorgCaptureOptions::global(), || Connection::connect(None))?;
+        for window in [frame, self.window] {
+            conn.send_request(&ChangeWindowAttributes {
+                window,
+                value_list: &[Cw::EventMask(EventMask::STRUCTURE_NOTIFY)],
+            });
+        }
+
+        // Per ICCCM clients restack their own window, a window manager moves the frame along
+        conn.send_and_check_request(&ConfigureWindow {
+            window: self.window,
+            value_list: &[ConfigWindow::StackMode(StackMode::Above)],
+        })
+        .map_err(xcb::Error::from)?;
+
+        // Restacking the frame reports a ConfigureNotify on it, a window manager declining
+        // the request answers with a synthetic one on the client window
+        let deadline = Instant::now() + RAISE_TIMEOUT;
+        loop {
+            while let Some(event) = conn.poll_for_event()? {
+                if let xcb::Event::X(x::Event::ConfigureNotify(event)) = event
+                    && (event.window() == frame || event.window() == self.window)
+                {
+                    return Ok(Some(above));
+                }
+            }
+
+            if Instant::now() >= deadline {
+                log::debug!(
+                    "window manager didn't restack window {} within {:?}",
+                    self.window.resource_id(),
+                    RAISE_TIMEOUT
+                );
+                return Ok(Some(above));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Stack the window's frame right below `above` again, as returned by [`ImplWindow::raise`].
+    pub(crate) fn restack_below(&self, above: Window) -> XCapResult<()> {
+        let (conn, _) = get_xcb_connection_and_index()?;
+        let frame = get_frame_window(&self.window)?;
+
+        conn.send_and_check_request(&ConfigureWindow {
+            window: frame,
+            value_list: &[
+                ConfigWindow::Sibling(above),
+                ConfigWindow::StackMode(StackMode::Below),
+            ],
+        })
+        .map_err(xcb::Error::from)?;
+
+        Ok(())
+    }
+
+    pub fn capture_image_raised(&self, restore: bool) -> XCapResult<RgbaImage> {
+        capture_window_raised(self, restore)
+    }
+
+    pub fn capture_image_with_frame(&self) -> XCapResult<RgbaImage> {
+        capture_window_frame(self)
+    }
+
+    pub fn capture_raw(&self) -> XCapResult<RawImage> {
+        capture_window_raw(self)
+    }
 }
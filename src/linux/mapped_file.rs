@@ -0,0 +1,84 @@
+use std::{fs::File, io, os::fd::AsRawFd, path::Path, ptr, slice};
+
+use crate::error::XCapResult;
+
+/// A file of a fixed size mapped read-write into memory, writes land in the page cache and
+/// are flushed to the file without ever holding the whole content in anonymous memory.
+pub(crate) struct MappedFile {
+    addr: *mut u8,
+    len: usize,
+}
+
+impl MappedFile {
+    /// Create or truncate the file at `path` to `len` bytes and map it.
+    pub(crate) fn create(path: &Path, len: usize) -> XCapResult<MappedFile> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+
+        // The mapping keeps the file referenced, the descriptor can be closed right away
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(MappedFile {
+            addr: addr as *mut u8,
+            len,
+        })
+    }
+
+    pub(crate) fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.addr, self.len) }
+    }
+
+    /// Write the dirty pages back to the file, reporting I/O errors unmapping would swallow.
+    pub(crate) fn flush(&self) -> XCapResult<()> {
+        if unsafe { libc::msync(self.addr as *mut libc::c_void, self.len, libc::MS_SYNC) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.addr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn test_mapped_file() {
+        let path = env::temp_dir().join(format!("xcap-mapped-file-{}", process::id()));
+
+        let mut mapped_file = MappedFile::create(&path, 6).unwrap();
+        mapped_file.data_mut().copy_from_slice(b"pixels");
+        mapped_file.flush().unwrap();
+        drop(mapped_file);
+
+        assert_eq!(fs::read(&path).unwrap(), b"pixels");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,16 @@
 mod capture;
+pub mod damage_capturer;
+pub mod float;
+pub mod frame_stream;
+mod mapped_file;
+pub mod nv12;
+pub mod planar;
+pub mod shared_capturer;
+mod swizzle;
 pub mod utils;
 mod wayland_capture;
 mod wayland_video_recorder;
+mod wayland_window_capture;
 pub mod xorg_capture;
 mod xorg_video_recorder;
 
@@ -0,0 +1,104 @@
+/// Coefficients used to derive limited range YUV from RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum YuvMatrix {
+    /// Standard definition content, what most encoders assume when untagged
+    #[default]
+    Bt601,
+    /// High definition content
+    Bt709,
+}
+
+impl YuvMatrix {
+    /// Y, U and V rows of the 8 bits fixed point matrix, `>> 8` after multiplying.
+    fn coefficients(&self) -> [[i32; 3]; 3] {
+        match self {
+            YuvMatrix::Bt601 => [[66, 129, 25], [-38, -74, 112], [112, -94, -18]],
+            YuvMatrix::Bt709 => [[47, 157, 16], [-26, -87, 112], [112, -102, -10]],
+        }
+    }
+}
+
+/// Frame in NV12 layout: a full resolution Y plane followed by a half resolution plane of
+/// interleaved `U, V` pairs, each 2x2 pixel block sharing one pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nv12Image {
+    pub width: u32,
+    pub height: u32,
+    pub y: Vec<u8>,
+    pub y_stride: usize,
+    pub uv: Vec<u8>,
+    pub uv_stride: usize,
+}
+
+fn dot([cr, cg, cb]: [i32; 3], (r, g, b): (i32, i32, i32)) -> i32 {
+    (cr * r + cg * g + cb * b + 128) >> 8
+}
+
+/// Build an NV12 frame reading every source pixel once through `read_pixel`, so the planes
+/// are computed straight from the source without an intermediate RGBA buffer. Odd widths
+/// and heights average the pixels the last block actually covers.
+pub(crate) fn to_nv12<F>(width: u32, height: u32, matrix: YuvMatrix, read_pixel: F) -> Nv12Image
+where
+    F: Fn(u32, u32) -> (u8, u8, u8, u8),
+{
+    let [y_row, u_row, v_row] = matrix.coefficients();
+    let y_stride = width as usize;
+    let uv_stride = width.div_ceil(2) as usize * 2;
+
+    let mut y_plane = vec![0; y_stride * height as usize];
+    let mut uv_plane = vec![0; uv_stride * height.div_ceil(2) as usize];
+
+    for block_y in (0..height).step_by(2) {
+        for block_x in (0..width).step_by(2) {
+            let mut sum = (0, 0, 0);
+            let mut count = 0;
+
+            for y in block_y..(block_y + 2).min(height) {
+                for x in block_x..(block_x + 2).min(width) {
+                    let (r, g, b, _) = read_pixel(x, y);
+                    let rgb = (r as i32, g as i32, b as i32);
+
+                    y_plane[y as usize * y_stride + x as usize] = (16 + dot(y_row, rgb)) as u8;
+
+                    sum = (sum.0 + rgb.0, sum.1 + rgb.1, sum.2 + rgb.2);
+                    count += 1;
+                }
+            }
+
+            let average = (sum.0 / count, sum.1 / count, sum.2 / count);
+            let index = (block_y / 2) as usize * uv_stride + block_x as usize;
+            uv_plane[index] = (128 + dot(u_row, average)) as u8;
+            uv_plane[index + 1] = (128 + dot(v_row, average)) as u8;
+        }
+    }
+
+    Nv12Image {
+        width,
+        height,
+        y: y_plane,
+        y_stride,
+        uv: uv_plane,
+        uv_stride,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_nv12() {
+        let white = to_nv12(2, 2, YuvMatrix::Bt601, |_, _| (255, 255, 255, 255));
+        assert_eq!(white.y, [235; 4]);
+        assert_eq!(white.uv, [128, 128]);
+
+        let black = to_nv12(2, 2, YuvMatrix::Bt709, |_, _| (0, 0, 0, 255));
+        assert_eq!(black.y, [16; 4]);
+        assert_eq!(black.uv, [128, 128]);
+
+        let red = to_nv12(3, 1, YuvMatrix::Bt601, |_, _| (255, 0, 0, 255));
+        assert_eq!((red.y_stride, red.uv_stride), (3, 4));
+        assert_eq!(red.y, [82; 3]);
+        assert_eq!(red.uv, [90, 240, 90, 240]);
+    }
+}
@@ -0,0 +1,58 @@
+/// Frame with every channel in its own plane, `width * height` bytes each, for filters that
+/// process one channel at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanarImage {
+    pub width: u32,
+    pub height: u32,
+    pub r: Vec<u8>,
+    pub g: Vec<u8>,
+    pub b: Vec<u8>,
+    pub a: Vec<u8>,
+}
+
+/// Split every source pixel read through `read_pixel` straight into the four planes,
+/// without an interleaved RGBA buffer in between.
+pub(crate) fn to_planar<F>(width: u32, height: u32, read_pixel: F) -> PlanarImage
+where
+    F: Fn(u32, u32) -> (u8, u8, u8, u8),
+{
+    let len = width as usize * height as usize;
+    let mut planes = [vec![0; len], vec![0; len], vec![0; len], vec![0; len]];
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y as usize * width as usize + x as usize;
+            let (r, g, b, a) = read_pixel(x, y);
+
+            for (plane, value) in planes.iter_mut().zip([r, g, b, a]) {
+                plane[index] = value;
+            }
+        }
+    }
+
+    let [r, g, b, a] = planes;
+
+    PlanarImage {
+        width,
+        height,
+        r,
+        g,
+        b,
+        a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_planar() {
+        let planar = to_planar(2, 2, |x, y| (x as u8, y as u8, 7, 255));
+
+        assert_eq!(planar.r, [0, 1, 0, 1]);
+        assert_eq!(planar.g, [0, 0, 1, 1]);
+        assert_eq!(planar.b, [7; 4]);
+        assert_eq!(planar.a, [255; 4]);
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    sync::{
+        Mutex, MutexGuard, TryLockError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    Monitor,
+    error::{XCapError, XCapResult},
+};
+
+use super::{
+    capture::{capture_monitor, capture_region, get_root_region},
+    utils::wayland_detect,
+    xorg_capture::{CaptureConnection, fit_factor},
+};
+
+/// What [`SharedCapturer`] does with a capture requested before its minimum interval elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Sleep until the interval elapsed, then capture
+    Block,
+    /// Fail with [`XCapError::RateLimited`] without touching the X server
+    Error,
+}
+
+/// Capturer that can be shared between threads, e.g. behind an `Arc` in a thread pool.
+///
+/// It holds a fixed pool of X connections, each behind a mutex. A capture takes the first
+/// idle connection, or waits on one picked round robin when all are busy. Requests on one
+/// connection are serialized by the X server anyway, so a pool of a single connection
+/// behaves like a single thread calling [`Monitor::capture_image`]; more connections
+/// only help while the server can interleave the work, which depends on the server and on
+/// the size of the captures. `examples/shared_capturer.rs` measures both setups.
+///
+/// On Wayland the pool is unused and captures go through the portal as usual.
+///
+/// [`SharedCapturer::with_min_interval`] caps the capture rate across all threads, so a
+/// tight recording loop can't flood the server and starve its other clients, and
+/// [`SharedCapturer::with_max_dimensions`] caps the size of the images it hands out.
+pub struct SharedCapturer {
+    connections: Vec<Mutex<Option<CaptureConnection>>>,
+    next: AtomicUsize,
+    rate_limit: Option<(Duration, RateLimitMode)>,
+    last_capture: Mutex<Option<Instant>>,
+    max_dimensions: Option<(u32, u32)>,
+}
+
+/// Time left until a capture is allowed again, `None` when it may start right away.
+fn remaining_interval(last: Option<Instant>, interval: Duration, now: Instant) -> Option<Duration> {
+    let allowed_at = last? + interval;
+
+    (now < allowed_at).then(|| allowed_at - now)
+}
+
+/// Average every `factor` x `factor` block of `image` into one pixel, dropping the right and
+/// bottom edge pixels that don't fill a whole block.
+fn box_downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let width = (image.width() / factor).max(1);
+    let height = (image.height() / factor).max(1);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u32; 4];
+        let mut count = 0;
+
+        for src_y in y * factor..((y + 1) * factor).min(image.height()) {
+            for src_x in x * factor..((x + 1) * factor).min(image.width()) {
+                let pixel = image.get_pixel(src_x, src_y);
+                for (sum, &channel) in sum.iter_mut().zip(&pixel.0) {
+                    *sum += channel as u32;
+                }
+                count += 1;
+            }
+        }
+
+        Rgba(sum.map(|sum| ((sum + count / 2) / count) as u8))
+    })
+}
+
+impl SharedCapturer {
+    /// Create a capturer with `pool_size` connections, opened lazily on first use.
+    pub fn new(pool_size: usize) -> XCapResult<SharedCapturer> {
+        if pool_size == 0 {
+            return Err(XCapError::new("Pool size must be greater than 0"));
+        }
+
+        Ok(SharedCapturer {
+            connections: (0..pool_size).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+            rate_limit: None,
+            last_capture: Mutex::new(None),
+            max_dimensions: None,
+        })
+    }
+
+    /// Start captures at least `interval` apart, handling earlier ones according to `mode`.
+    pub fn with_min_interval(self, interval: Duration, mode: RateLimitMode) -> SharedCapturer {
+        SharedCapturer {
+            rate_limit: Some((interval, mode)),
+            ..self
+        }
+    }
+
+    /// Downscale captures larger than `max_dimensions` by the smallest integer factor that
+    /// fits them, averaging each block of pixels, so the aspect ratio is kept. The returned
+    /// image's dimensions are the actual output size.
+    pub fn with_max_dimensions(self, max_dimensions: (u32, u32)) -> SharedCapturer {
+        SharedCapturer {
+            max_dimensions: Some(max_dimensions),
+            ..self
+        }
+    }
+
+    fn fit(&self, image: RgbaImage) -> RgbaImage {
+        let Some(max_dimensions) = self.max_dimensions else {
+            return image;
+        };
+
+        match fit_factor(image.width(), image.height(), max_dimensions) {
+            1 => image,
+            factor => box_downsample(&image, factor),
+        }
+    }
+
+    fn throttle(&self) -> XCapResult<()> {
+        let Some((interval, mode)) = self.rate_limit else {
+            return Ok(());
+        };
+
+        // Held while blocking, so waiting threads are let through one interval apart
+        let mut last_capture = self
+            .last_capture
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        if let Some(remaining) = remaining_interval(*last_capture, interval, Instant::now()) {
+            match mode {
+                RateLimitMode::Block => thread::sleep(remaining),
+                RateLimitMode::Error => {
+                    return Err(XCapError::RateLimited {
+                        retry_after: remaining,
+                    });
+                }
+            }
+        }
+
+        *last_capture = Some(Instant::now());
+
+        Ok(())
+    }
+
+    fn acquire(&self) -> XCapResult<MutexGuard<'_, Option<CaptureConnection>>> {
+        let mut connection = None;
+
+        for item in &self.connections {
+            match item.try_lock() {
+                Ok(guard) => {
+                    connection = Some(guard);
+                    break;
+                }
+                Err(TryLockError::Poisoned(err)) => {
+                    connection = Some(err.into_inner());
+                    break;
+                }
+                Err(TryLockError::WouldBlock) => continue,
+            }
+        }
+
+        let mut connection = match connection {
+            Some(connection) => connection,
+            None => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+                self.connections[index]
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+            }
+        };
+
+        if connection.as_ref().is_none_or(|item| item.is_broken()) {
+            *connection = Some(CaptureConnection::new()?);
+        }
+
+        Ok(connection)
+    }
+
+    pub fn capture_monitor(&self, monitor: &Monitor) -> XCapResult<RgbaImage> {
+        self.throttle()?;
+
+        if wayland_detect() {
+            return capture_monitor(&monitor.impl_monitor).map(|image| self.fit(image));
+        }
+
+        let monitor_info_buf = monitor.impl_monitor.monitor_info_buf()?;
+        let screen_buf = monitor.impl_monitor.screen_buf()?;
+
+        let image = self
+            .acquire()?
+            .as_mut()
+            .ok_or(XCapError::new("Capture connection is not initialized"))?
+            .capture(
+                screen_buf.root(),
+                monitor_info_buf.x() as i32,
+                monitor_info_buf.y() as i32,
+                monitor_info_buf.width() as u32,
+                monitor_info_buf.height() as u32,
+            )?;
+
+        Ok(self.fit(image))
+    }
+
+    pub fn capture_region(
+        &self,
+        monitor: &Monitor,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.throttle()?;
+
+        if wayland_detect() {
+            return capture_region(&monitor.impl_monitor, x, y, width, height)
+                .map(|image| self.fit(image));
+        }
+
+        let monitor_info_buf = monitor.impl_monitor.monitor_info_buf()?;
+        let screen_buf = monitor.impl_monitor.screen_buf()?;
+        let (root_x, root_y) =
+            get_root_region(&monitor_info_buf, &screen_buf, x, y, width, height)?;
+
+        let image = self
+            .acquire()?
+            .as_mut()
+            .ok_or(XCapError::new("Capture connection is not initialized"))?
+            .capture(screen_buf.root(), root_x, root_y, width, height)?;
+
+        Ok(self.fit(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_capturer_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<SharedCapturer>();
+        assert!(SharedCapturer::new(0).is_err());
+    }
+
+    #[test]
+    fn test_remaining_interval() {
+        let interval = Duration::from_millis(100);
+        let now = Instant::now();
+
+        assert_eq!(remaining_interval(None, interval, now), None);
+        assert_eq!(
+            remaining_interval(Some(now), interval, now + Duration::from_millis(40)),
+            Some(Duration::from_millis(60))
+        );
+        assert_eq!(
+            remaining_interval(Some(now), interval, now + interval),
+            None
+        );
+    }
+
+    #[test]
+    fn test_box_downsample() {
+        assert_eq!(fit_factor(1920, 1080, (1920, 1080)), 1);
+        assert_eq!(fit_factor(3840, 2160, (1920, 1080)), 2);
+        // The tighter of the two bounds decides
+        assert_eq!(fit_factor(5120, 1440, (1920, 1080)), 3);
+
+        let image = RgbaImage::from_fn(5, 2, |x, _| {
+            Rgba(if x % 2 == 0 {
+                [0, 0, 0, 255]
+            } else {
+                [255, 100, 1, 255]
+            })
+        });
+        let downsampled = box_downsample(&image, 2);
+        assert_eq!(downsampled.dimensions(), (2, 1));
+        assert_eq!(downsampled.get_pixel(0, 0), &Rgba([128, 50, 1, 255]));
+    }
+
+    #[test]
+    fn test_throttle() {
+        let capturer = SharedCapturer::new(1)
+            .unwrap()
+            .with_min_interval(Duration::from_secs(60), RateLimitMode::Error);
+
+        assert!(capturer.throttle().is_ok());
+        assert!(matches!(
+            capturer.throttle(),
+            Err(XCapError::RateLimited { .. })
+        ));
+    }
+}
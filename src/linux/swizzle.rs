@@ -0,0 +1,141 @@
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// `pshufb` indices turning four `B, G, R, X` pixels into `R, G, B, X`
+#[cfg(target_arch = "x86_64")]
+const BGRX_TO_RGBX: [u8; 16] = [2, 1, 0, 3, 6, 5, 4, 7, 10, 9, 8, 11, 14, 13, 12, 15];
+
+/// Depth 24/32 pixels stored as 4 bytes in `B, G, R, X` order, the byte swap
+/// is done on whole slices instead of going through `get_pixel24_32_rgba`.
+/// With `keep_alpha` the fourth byte is the alpha channel, otherwise it's padding.
+///
+/// On x86_64 the bulk is shuffled 16 pixels at a time with AVX2, or 8 with SSSE3, picked
+/// at runtime; the pixels left over and other CPUs go through the scalar loop.
+pub(crate) fn bgrx_to_rgba(src: &[u8], rgba: &mut [u8], keep_alpha: bool) {
+    #[cfg(target_arch = "x86_64")]
+    let done = if is_x86_feature_detected!("avx2") {
+        // Safe to call, the CPU supports the instructions the function is compiled with
+        unsafe { bgrx_to_rgba_avx2(src, rgba, keep_alpha) }
+    } else if is_x86_feature_detected!("ssse3") {
+        unsafe { bgrx_to_rgba_ssse3(src, rgba, keep_alpha) }
+    } else {
+        0
+    };
+    #[cfg(not(target_arch = "x86_64"))]
+    let done = 0;
+
+    bgrx_to_rgba_scalar(&src[done..], &mut rgba[done..], keep_alpha);
+}
+
+fn bgrx_to_rgba_scalar(src: &[u8], rgba: &mut [u8], keep_alpha: bool) {
+    let (src, _) = src.as_chunks::<4>();
+    let (rgba, _) = rgba.as_chunks_mut::<4>();
+
+    for (dst, &[b, g, r, x]) in rgba.iter_mut().zip(src) {
+        *dst = [r, g, b, if keep_alpha { x } else { 255 }];
+    }
+}
+
+/// Value or-ed into every pixel, setting the padding byte to an opaque alpha.
+#[cfg(target_arch = "x86_64")]
+fn alpha_fill(keep_alpha: bool) -> i32 {
+    if keep_alpha {
+        0
+    } else {
+        0xff00_0000_u32 as i32
+    }
+}
+
+/// Swizzle whole blocks of 8 pixels, returning how many bytes were written.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+fn bgrx_to_rgba_ssse3(src: &[u8], rgba: &mut [u8], keep_alpha: bool) -> usize {
+    let (src, _) = src.as_chunks::<32>();
+    let (rgba, _) = rgba.as_chunks_mut::<32>();
+
+    let shuffle = unsafe { _mm_loadu_si128(BGRX_TO_RGBX.as_ptr().cast()) };
+    let alpha = _mm_set1_epi32(alpha_fill(keep_alpha));
+
+    let mut done = 0;
+    for (dst, src) in rgba.iter_mut().zip(src) {
+        // Unaligned loads and stores within the 32 byte arrays
+        unsafe {
+            let lo = _mm_loadu_si128(src.as_ptr().cast());
+            let hi = _mm_loadu_si128(src.as_ptr().add(16).cast());
+            let lo = _mm_or_si128(_mm_shuffle_epi8(lo, shuffle), alpha);
+            let hi = _mm_or_si128(_mm_shuffle_epi8(hi, shuffle), alpha);
+            _mm_storeu_si128(dst.as_mut_ptr().cast(), lo);
+            _mm_storeu_si128(dst.as_mut_ptr().add(16).cast(), hi);
+        }
+        done += 32;
+    }
+
+    done
+}
+
+/// Swizzle whole blocks of 16 pixels, returning how many bytes were written.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn bgrx_to_rgba_avx2(src: &[u8], rgba: &mut [u8], keep_alpha: bool) -> usize {
+    let (src, _) = src.as_chunks::<64>();
+    let (rgba, _) = rgba.as_chunks_mut::<64>();
+
+    // vpshufb shuffles each 128 bit lane on its own, both lanes use the same indices
+    let shuffle =
+        _mm256_broadcastsi128_si256(unsafe { _mm_loadu_si128(BGRX_TO_RGBX.as_ptr().cast()) });
+    let alpha = _mm256_set1_epi32(alpha_fill(keep_alpha));
+
+    let mut done = 0;
+    for (dst, src) in rgba.iter_mut().zip(src) {
+        // Unaligned loads and stores within the 64 byte arrays
+        unsafe {
+            let lo = _mm256_loadu_si256(src.as_ptr().cast());
+            let hi = _mm256_loadu_si256(src.as_ptr().add(32).cast());
+            let lo = _mm256_or_si256(_mm256_shuffle_epi8(lo, shuffle), alpha);
+            let hi = _mm256_or_si256(_mm256_shuffle_epi8(hi, shuffle), alpha);
+            _mm256_storeu_si256(dst.as_mut_ptr().cast(), lo);
+            _mm256_storeu_si256(dst.as_mut_ptr().add(32).cast(), hi);
+        }
+        done += 64;
+    }
+
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bgrx_to_rgba_simd() {
+        // 37 pixels, whole SIMD blocks plus a scalar remainder
+        let src: Vec<u8> = (0..148).map(|value| value as u8).collect();
+
+        for keep_alpha in [false, true] {
+            let mut expected = vec![0; src.len()];
+            bgrx_to_rgba_scalar(&src, &mut expected, keep_alpha);
+
+            let mut rgba = vec![0; src.len()];
+            bgrx_to_rgba(&src, &mut rgba, keep_alpha);
+            assert_eq!(rgba, expected);
+
+            #[cfg(target_arch = "x86_64")]
+            for (supported, swizzle) in [
+                (
+                    is_x86_feature_detected!("ssse3"),
+                    bgrx_to_rgba_ssse3 as unsafe fn(&[u8], &mut [u8], bool) -> usize,
+                ),
+                (is_x86_feature_detected!("avx2"), bgrx_to_rgba_avx2),
+            ] {
+                if !supported {
+                    continue;
+                }
+
+                let mut rgba = vec![0; src.len()];
+                let done = unsafe { swizzle(&src, &mut rgba, keep_alpha) };
+                bgrx_to_rgba_scalar(&src[done..], &mut rgba[done..], keep_alpha);
+                assert_eq!(rgba, expected);
+            }
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use std::{
     env::{self, var_os},
+    io, mem,
+    os::fd::RawFd,
     path::{Path, PathBuf},
+    sync::RwLock,
+    thread,
+    time::Duration,
 };
 
 use image::{RgbaImage, open};
@@ -9,7 +14,7 @@ use percent_encoding::percent_decode_str;
 use serde::Deserialize;
 use url::Url;
 use xcb::{
-    ConnResult, Connection as XcbConnection, Xid,
+    ConnError, ConnResult, Connection as XcbConnection, Xid,
     randr::{GetMonitors, MonitorInfoBuf, Output},
     x::{Atom, InternAtom, ScreenBuf},
 };
@@ -21,16 +26,108 @@ use zbus::{
 
 use crate::{XCapError, error::XCapResult};
 
+/// How X connections are opened and waited on, see [`XorgCaptureOptions::set_global`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorgCaptureOptions {
+    /// Extra attempts after a failed connect, e.g. while a headless display is still starting.
+    /// None by default, so a missing display is reported right away.
+    pub connect_retries: u32,
+    /// Wait before the first retry, doubled after every further failure.
+    pub retry_delay: Duration,
+    /// Give up on an image reply after this long with [`crate::XCapError::Timeout`], so a
+    /// stalled server can't hang the capturing thread. `None` waits indefinitely.
+    pub capture_timeout: Option<Duration>,
+    /// Start every `GetImage` right after the next vblank, using the Present extension, so
+    /// frames aren't read while scanout updates them. Adds up to a refresh interval of
+    /// latency per capture, which is why it is off by default. Servers without Present are
+    /// captured without the wait, a warning is logged the first time.
+    pub vsync: bool,
+}
+
+impl Default for XorgCaptureOptions {
+    fn default() -> Self {
+        XORG_CAPTURE_OPTIONS_DEFAULT
+    }
+}
+
+const XORG_CAPTURE_OPTIONS_DEFAULT: XorgCaptureOptions = XorgCaptureOptions {
+    connect_retries: 0,
+    retry_delay: Duration::from_millis(100),
+    capture_timeout: None,
+    vsync: false,
+};
+
+static XORG_CAPTURE_OPTIONS: RwLock<XorgCaptureOptions> = RwLock::new(XORG_CAPTURE_OPTIONS_DEFAULT);
+
+impl XorgCaptureOptions {
+    /// Use these options for every connection opened from now on. Capture connections are
+    /// opened on first use and then reused, so set them before the first capture for them to
+    /// apply to all of them.
+    pub fn set_global(self) {
+        *XORG_CAPTURE_OPTIONS
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = self;
+    }
+
+    pub fn global() -> XorgCaptureOptions {
+        *XORG_CAPTURE_OPTIONS
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Run `connect` until it succeeds or the retries configured in `options` are used up.
+pub fn connect_with_retry<T, F>(options: &XorgCaptureOptions, connect: F) -> ConnResult<T>
+where
+    F: Fn() -> ConnResult<T>,
+{
+    let mut delay = options.retry_delay;
+
+    for attempt in 0..options.connect_retries {
+        match connect() {
+            Ok(conn) => return Ok(conn),
+            Err(err) => {
+                log::debug!("X connection attempt {} failed: {}", attempt + 1, err);
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+        }
+    }
+
+    connect()
+}
+
 lazy_static! {
     static ref XCB_CONNECTION_AND_INDEX: ConnResult<(XcbConnection, i32)> = {
         let display_name = env::var("DISPLAY").unwrap_or("DISPLAY:1".to_string());
-        XcbConnection::connect(Some(display_name.as_str()))
+        connect_with_retry(&XorgCaptureOptions::global(), || {
+            XcbConnection::connect(Some(display_name.as_str()))
+        })
     };
     static ref ZBUS_CONNECTION: ZBusResult<ZBusConnection> = ZBusConnection::session();
 }
 
 pub fn get_xcb_connection_and_index() -> XCapResult<&'static (XcbConnection, i32)> {
-    XCB_CONNECTION_AND_INDEX.as_ref().map_err(XCapError::new)
+    XCB_CONNECTION_AND_INDEX
+        .as_ref()
+        .map_err(|err| XCapError::XcbConnError(copy_conn_error(err)))
+}
+
+/// `ConnError` is neither `Clone` nor `Copy`, but the cached connect error has to be handed
+/// out on every call. All its variants are unit variants.
+fn copy_conn_error(err: &ConnError) -> ConnError {
+    match err {
+        ConnError::Connection => ConnError::Connection,
+        ConnError::ClosedExtNotSupported => ConnError::ClosedExtNotSupported,
+        ConnError::ClosedMemInsufficient => ConnError::ClosedMemInsufficient,
+        ConnError::ClosedReqLenExceed => ConnError::ClosedReqLenExceed,
+        ConnError::ClosedParseErr => ConnError::ClosedParseErr,
+        ConnError::ClosedInvalidScreen => ConnError::ClosedInvalidScreen,
+        ConnError::ClosedFdPassingFailed => ConnError::ClosedFdPassingFailed,
+        // Variants behind xcb features this crate doesn't enable
+        #[allow(unreachable_patterns)]
+        _ => ConnError::Connection,
+    }
 }
 
 pub fn get_zbus_connection() -> XCapResult<&'static ZBusConnection> {
@@ -53,23 +150,70 @@ pub fn wayland_detect() -> bool {
     xdg_session_type.eq("wayland") || wayland_display.to_lowercase().contains("wayland")
 }
 
-pub fn get_current_screen_buf() -> XCapResult<ScreenBuf> {
-    let (conn, index) = get_xcb_connection_and_index()?;
+/// Whether `fd` is a TCP socket, as X connections to other hosts and those forwarded by
+/// `ssh -X` are. A local server is reached through a unix socket instead.
+pub fn is_tcp_socket(fd: RawFd) -> io::Result<bool> {
+    let mut addr = unsafe { mem::zeroed::<libc::sockaddr_storage>() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockname(
+            fd,
+            &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(matches!(
+        addr.ss_family as libc::c_int,
+        libc::AF_INET | libc::AF_INET6
+    ))
+}
+
+/// Display server that captures go through, see [`current_backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    Xorg,
+    /// Monitor captures may need portal permission, windows are captured through the
+    /// compositor's ext-image-copy-capture toplevel sources where it offers them
+    Wayland,
+}
+
+/// Backend detected from the session environment, the same check every capture makes.
+pub fn current_backend() -> Backend {
+    if wayland_detect() {
+        Backend::Wayland
+    } else {
+        Backend::Xorg
+    }
+}
+
+/// Screen `screen` of the X server, the `N` of a `:0.N` display name.
+pub fn get_screen_buf(screen: usize) -> XCapResult<ScreenBuf> {
+    let (conn, _) = get_xcb_connection_and_index()?;
 
     let setup = conn.get_setup();
 
     let screen = setup
         .roots()
-        .nth(*index as usize)
+        .nth(screen)
         .ok_or_else(|| XCapError::new("Not found screen"))?;
 
     Ok(screen.to_owned())
 }
 
-pub fn get_monitor_info_buf(output: Output) -> XCapResult<MonitorInfoBuf> {
-    let (conn, _) = get_xcb_connection_and_index()?;
+/// The screen named by the display, `:0` and `:0.0` mean the first one.
+pub fn get_current_screen_buf() -> XCapResult<ScreenBuf> {
+    let (_, index) = get_xcb_connection_and_index()?;
 
-    let screen_buf = get_current_screen_buf()?;
+    get_screen_buf(*index as usize)
+}
+
+pub fn get_monitor_info_buf(output: Output, screen_buf: &ScreenBuf) -> XCapResult<MonitorInfoBuf> {
+    let (conn, _) = get_xcb_connection_and_index()?;
 
     let get_monitors_cookie = conn.send_request(&GetMonitors {
         window: screen_buf.root(),
@@ -185,3 +329,63 @@ where
 
     Err(XCapError::new(format!("Response code is {}", code)))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use std::{
+        error::Error,
+        net::TcpListener,
+        os::{fd::AsRawFd, unix::net::UnixStream},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_connect_with_retry() {
+        let options = XorgCaptureOptions {
+            connect_retries: 2,
+            retry_delay: Duration::ZERO,
+            capture_timeout: None,
+            vsync: false,
+        };
+
+        let attempts = Cell::new(0);
+        let result = connect_with_retry(&options, || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(ConnError::Connection)
+            } else {
+                Ok(attempts.get())
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        attempts.set(0);
+        let result: ConnResult<()> = connect_with_retry(&options, || {
+            attempts.set(attempts.get() + 1);
+            Err(ConnError::Connection)
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[test]
+    fn test_is_tcp_socket() {
+        let (unix, _) = UnixStream::pair().unwrap();
+        assert!(!is_tcp_socket(unix.as_raw_fd()).unwrap());
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(is_tcp_socket(tcp.as_raw_fd()).unwrap());
+    }
+
+    #[test]
+    fn test_conn_error_source() {
+        let err = XCapError::from(copy_conn_error(&ConnError::ClosedParseErr));
+
+        let source = err.source().unwrap();
+        assert_eq!(source.to_string(), ConnError::ClosedParseErr.to_string());
+        assert!(err.to_string().ends_with(&source.to_string()));
+    }
+}
@@ -9,11 +9,14 @@ use zbus::{
 };
 
 use crate::{
-    error::XCapResult,
+    error::{XCapError, XCapResult},
     platform::utils::{get_zbus_portal_request, safe_uri_to_path, wait_zbus_response},
 };
 
-use super::utils::{get_zbus_connection, png_to_rgba_image};
+use super::{
+    utils::{get_zbus_connection, png_to_rgba_image},
+    wayland_video_recorder::{screencast_available, screencast_capture},
+};
 
 fn org_gnome_shell_screenshot(
     conn: &Connection,
@@ -141,6 +144,23 @@ pub fn wayland_capture(x: i32, y: i32, width: i32, height: i32) -> XCapResult<Rg
         .or_else(|e| {
             log::debug!("org_freedesktop_portal_screenshot failed {}", e);
             wlroots_screenshot(x, y, width, height)
+        })
+        .or_else(|err| {
+            // Portal only compositors such as GNOME and KDE lack screencopy but cast the
+            // screen over PipeWire. Otherwise the error is kept, it decides the X11 fallback.
+            let lacks_screencopy = matches!(
+                err,
+                XCapError::LibwayshotError(libwayshot_xcap::Error::ProtocolNotFound(_))
+            );
+            if lacks_screencopy && screencast_available() {
+                log::debug!(
+                    "wlroots_screenshot failed {}, using the ScreenCast portal",
+                    err
+                );
+                screencast_capture(x, y, width, height)
+            } else {
+                Err(err)
+            }
         });
 
     drop(lock);
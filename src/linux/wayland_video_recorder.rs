@@ -1,20 +1,25 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
     io::Cursor,
+    os::fd,
+    rc::Rc,
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, Sender},
     },
     thread,
 };
 
+use image::{RgbaImage, imageops};
 use pipewire::{
     context::Context,
     keys::{MEDIA_CATEGORY, MEDIA_ROLE, MEDIA_TYPE},
     main_loop::MainLoop,
     properties,
     spa::{
+        buffer::Data,
         param::{
             ParamType,
             format::{FormatProperties, MediaSubtype, MediaType},
@@ -24,7 +29,7 @@ use pipewire::{
         pod::{self, Pod, serialize::PodSerializer},
         utils::{Direction, Fraction, Rectangle, SpaTypes},
     },
-    stream::{Stream, StreamFlags},
+    stream::{Stream, StreamFlags, StreamRef, StreamState},
 };
 use serde::Deserialize;
 use zbus::{
@@ -37,6 +42,7 @@ use crate::{XCapError, XCapResult, video_recorder::Frame};
 use super::{
     impl_monitor::ImplMonitor,
     utils::{get_zbus_connection, get_zbus_portal_request, wait_zbus_response},
+    xorg_capture::buffer_len,
 };
 
 #[allow(dead_code)]
@@ -54,10 +60,23 @@ pub struct ScreenCastStartStream {
 #[zvariant(signature = "dict")]
 pub struct ScreenCastStartResponse {
     pub streams: Option<Vec<(u32, ScreenCastStartStream)>>,
-    #[allow(dead_code)]
     pub restore_token: Option<String>,
 }
 
+/// Options of [`ScreenCast::select_sources`].
+#[derive(Debug, Default)]
+pub struct SelectSources<'a> {
+    /// Let the user pick several monitors
+    pub multiple: bool,
+    /// Have the portal remember the selection until revoked, it hands out a restore token
+    pub persist: bool,
+    /// Token of an earlier session, restores its selection without asking the user again
+    pub restore_token: Option<&'a str>,
+}
+
+/// `AvailableSourceTypes` bit of monitor sources
+const MONITOR_SOURCE: u32 = 1;
+
 /// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.ScreenCast.html
 pub struct ScreenCast<'a> {
     proxy: Proxy<'a>,
@@ -105,7 +124,11 @@ impl ScreenCast<'_> {
         Ok(session)
     }
 
-    pub fn select_sources(&self, session: &OwnedObjectPath) -> XCapResult<()> {
+    pub fn select_sources(
+        &self,
+        session: &OwnedObjectPath,
+        select_sources: SelectSources<'_>,
+    ) -> XCapResult<()> {
         let conn = get_zbus_connection()?;
 
         let mut options = HashMap::new();
@@ -114,8 +137,15 @@ impl ScreenCast<'_> {
         let portal_request = get_zbus_portal_request(conn, &handle_token)?;
 
         options.insert("handle_token", Value::from(handle_token));
-        options.insert("types", Value::from(1_u32));
-        options.insert("multiple", Value::from(false));
+        options.insert("types", Value::from(MONITOR_SOURCE));
+        options.insert("multiple", Value::from(select_sources.multiple));
+        if select_sources.persist {
+            // Persist until the permission is revoked
+            options.insert("persist_mode", Value::from(2_u32));
+        }
+        if let Some(restore_token) = select_sources.restore_token {
+            options.insert("restore_token", Value::from(restore_token));
+        }
 
         self.proxy
             .call_method("SelectSources", &(session, options))?;
@@ -140,15 +170,42 @@ impl ScreenCast<'_> {
         wait_zbus_response(&portal_request)
     }
 
-    #[allow(dead_code)]
-    pub fn open_pipe_wire_remote(&self, session: &OwnedObjectPath) -> XCapResult<OwnedFd> {
+    pub fn close(&self, session: &OwnedObjectPath) -> XCapResult<()> {
+        let conn = get_zbus_connection()?;
+        let proxy = Proxy::new(
+            conn,
+            "org.freedesktop.portal.Desktop",
+            session,
+            "org.freedesktop.portal.Session",
+        )?;
+
+        proxy.call_method("Close", &())?;
+
+        Ok(())
+    }
+
+    /// The PipeWire remote the session's streams are reachable on, sandboxed applications
+    /// can't connect to the default one.
+    pub fn open_pipe_wire_remote(&self, session: &OwnedObjectPath) -> XCapResult<fd::OwnedFd> {
         let options: HashMap<&str, Value<'_>> = HashMap::new();
         let fd: OwnedFd = self.proxy.call("OpenPipeWireRemote", &(session, options))?;
 
-        Ok(fd)
+        Ok(fd.into())
+    }
+
+    /// Bitmask of the source types the portal can cast, `1` being monitors.
+    pub fn available_source_types(&self) -> XCapResult<u32> {
+        Ok(self.proxy.get_property("AvailableSourceTypes")?)
     }
 }
 
+/// Whether a ScreenCast portal able to cast monitors is running.
+pub fn screencast_available() -> bool {
+    ScreenCast::new()
+        .and_then(|screen_cast| screen_cast.available_source_types())
+        .is_ok_and(|source_types| source_types & MONITOR_SOURCE != 0)
+}
+
 #[derive(Debug, Clone)]
 pub struct WaylandVideoRecorder {
     #[allow(dead_code)]
@@ -162,13 +219,189 @@ struct ListenerUserData {
     pub format: VideoInfoRaw,
 }
 
+fn on_param_changed(_: &StreamRef, user_data: &mut ListenerUserData, id: u32, param: Option<&Pod>) {
+    let Some(param) = param else {
+        return;
+    };
+
+    if id != ParamType::Format.as_raw() {
+        return;
+    }
+
+    let (media_type, media_subtype) = match format_utils::parse_format(param) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("Failed to parse format: {:?}", err);
+            return;
+        }
+    };
+
+    if media_type != MediaType::Video || media_subtype != MediaSubtype::Raw {
+        return;
+    }
+
+    if let Err(err) = user_data.format.parse(param) {
+        log::error!("Failed to parse format: {:?}", err);
+    }
+}
+
+fn bytes_per_pixel(format: VideoFormat) -> XCapResult<usize> {
+    match format {
+        VideoFormat::RGB => Ok(3),
+        VideoFormat::RGBA | VideoFormat::RGBx | VideoFormat::BGRx => Ok(4),
+        format => Err(XCapError::new(format!("Unsupported format: {:?}", format))),
+    }
+}
+
+/// Convert one frame in any of the formats offered by [`format_params`] to RGBA. Rows start
+/// at `offset` and follow each other every `stride` bytes, 0 meaning they are packed. The
+/// padding byte of the `x` formats is undefined, those pixels are made opaque.
+fn frame_to_rgba(
+    format: VideoFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    offset: usize,
+    stride: usize,
+) -> XCapResult<Vec<u8>> {
+    let row_len = width as usize * bytes_per_pixel(format)?;
+    let stride = if stride == 0 { row_len } else { stride };
+
+    let mut buf = vec![0; buffer_len(width, height, 4)?];
+    if buf.is_empty() {
+        return Ok(buf);
+    }
+
+    let expected = offset + stride * (height as usize - 1) + row_len;
+    if data.len() < expected {
+        return Err(XCapError::IncompleteImageData {
+            expected,
+            got: data.len(),
+        });
+    }
+
+    for (y, dst) in buf.chunks_exact_mut(width as usize * 4).enumerate() {
+        let start = offset + y * stride;
+        let src = &data[start..start + row_len];
+        let dst = dst.as_chunks_mut::<4>().0;
+
+        match format {
+            VideoFormat::RGB => {
+                for (dst, &[r, g, b]) in dst.iter_mut().zip(src.as_chunks::<3>().0) {
+                    *dst = [r, g, b, 255];
+                }
+            }
+            VideoFormat::RGBA => dst.as_flattened_mut().copy_from_slice(src),
+            VideoFormat::RGBx => {
+                for (dst, &[r, g, b, _]) in dst.iter_mut().zip(src.as_chunks::<4>().0) {
+                    *dst = [r, g, b, 255];
+                }
+            }
+            _ => {
+                for (dst, &[b, g, r, _]) in dst.iter_mut().zip(src.as_chunks::<4>().0) {
+                    *dst = [r, g, b, 255];
+                }
+            }
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Convert the frame in a dequeued buffer's data block, laid out as its chunk describes.
+/// `None` when the block isn't mapped.
+fn data_to_rgba(data: &mut Data, format: &VideoInfoRaw) -> Option<XCapResult<Vec<u8>>> {
+    let chunk = data.chunk();
+    let (offset, size, stride) = (
+        chunk.offset() as usize,
+        chunk.size() as usize,
+        chunk.stride(),
+    );
+    let bytes = data.data()?;
+    let bytes = &bytes[..(offset + size).min(bytes.len())];
+
+    let result = usize::try_from(stride)
+        .map_err(|_| XCapError::new("Bottom-up PipeWire frames are not supported"))
+        .and_then(|stride| {
+            let size = format.size();
+            frame_to_rgba(
+                format.format(),
+                size.width,
+                size.height,
+                bytes,
+                offset,
+                stride,
+            )
+        });
+
+    Some(result)
+}
+
+/// Serialized `EnumFormat` param listing the raw video formats [`frame_to_rgba`] converts.
+fn format_params() -> XCapResult<Vec<u8>> {
+    let obj = pod::object!(
+        SpaTypes::ObjectParamFormat,
+        ParamType::EnumFormat,
+        pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
+        pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
+        pod::property!(
+            FormatProperties::VideoFormat,
+            Choice,
+            Enum,
+            Id,
+            VideoFormat::RGB,
+            VideoFormat::RGBA,
+            VideoFormat::RGBx,
+            VideoFormat::BGRx,
+            // VideoFormat::YUY2,
+            // VideoFormat::I420,
+        ),
+        pod::property!(
+            FormatProperties::VideoSize,
+            Choice,
+            Range,
+            Rectangle,
+            Rectangle {
+                width: 128,
+                height: 128
+            },
+            Rectangle {
+                width: 1,
+                height: 1
+            },
+            Rectangle {
+                width: 4096,
+                height: 4096
+            }
+        ),
+        pod::property!(
+            FormatProperties::VideoFramerate,
+            Choice,
+            Range,
+            Fraction,
+            Fraction { num: 24, denom: 1 },
+            Fraction { num: 0, denom: 1 },
+            Fraction {
+                num: 1000,
+                denom: 1
+            }
+        ),
+    );
+    let values = PodSerializer::serialize(Cursor::new(Vec::new()), &pod::Value::Object(obj))
+        .map_err(XCapError::new)?
+        .0
+        .into_inner();
+
+    Ok(values)
+}
+
 impl WaylandVideoRecorder {
     pub fn new(monitor: ImplMonitor) -> XCapResult<(Self, Receiver<Frame>)> {
         let (sender, receiver) = mpsc::channel();
 
         let screen_cast = ScreenCast::new()?;
         let session = screen_cast.create_session()?;
-        screen_cast.select_sources(&session)?;
+        screen_cast.select_sources(&session, SelectSources::default())?;
         let response = screen_cast.start(&session)?;
 
         // 获取流节点ID
@@ -179,18 +412,20 @@ impl WaylandVideoRecorder {
             .ok_or(XCapError::new("Stream ID not found"))?
             .0;
 
+        let fd = screen_cast.open_pipe_wire_remote(&session)?;
+
         let recorder = Self {
             monitor,
             sender,
             is_running: Arc::new(AtomicBool::new(false)),
         };
 
-        recorder.pipewire_capturer(stream_id)?;
+        recorder.pipewire_capturer(fd, stream_id)?;
 
         Ok((recorder, receiver))
     }
 
-    pub fn pipewire_capturer(&self, stream_id: u32) -> XCapResult<()> {
+    pub fn pipewire_capturer(&self, fd: fd::OwnedFd, stream_id: u32) -> XCapResult<()> {
         let sender = self.sender.clone();
         let is_running = self.is_running.clone();
 
@@ -199,7 +434,7 @@ impl WaylandVideoRecorder {
 
             let main_loop = MainLoop::new(None)?;
             let context = Context::new(&main_loop)?;
-            let core = context.connect(None)?;
+            let core = context.connect_fd(fd, None)?;
 
             let user_data = ListenerUserData {
                 format: Default::default(),
@@ -217,31 +452,7 @@ impl WaylandVideoRecorder {
 
             let _listener = stream
                 .add_local_listener_with_user_data(user_data)
-                .param_changed(|_, user_data, id, param| {
-                    let Some(param) = param else {
-                        return;
-                    };
-
-                    if id != ParamType::Format.as_raw() {
-                        return;
-                    }
-
-                    let (media_type, media_subtype) = match format_utils::parse_format(param) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            log::error!("Failed to parse format: {:?}", err);
-                            return;
-                        }
-                    };
-
-                    if media_type != MediaType::Video || media_subtype != MediaSubtype::Raw {
-                        return;
-                    }
-
-                    if let Err(err) = user_data.format.parse(param) {
-                        log::error!("Failed to parse format: {:?}", err);
-                    }
-                })
+                .param_changed(on_param_changed)
                 .process(move |stream, user_data| {
                     let state = is_running.load(Ordering::Relaxed);
                     if !state {
@@ -250,106 +461,26 @@ impl WaylandVideoRecorder {
                     match stream.dequeue_buffer() {
                         None => log::info!("stream.dequeue_buffer() returned None"),
                         Some(mut buffer) => {
-                            let datas = buffer.datas_mut();
-                            if datas.is_empty() {
+                            let Some(data) = buffer.datas_mut().first_mut() else {
                                 return;
-                            }
+                            };
                             let size = user_data.format.size();
-                            if let Some(frame_data) = datas[0].data() {
-                                let buffer = match user_data.format.format() {
-                                    VideoFormat::RGB => {
-                                        let mut buf =
-                                            vec![0; (size.width * size.height * 4) as usize];
-                                        for (src, dst) in
-                                            frame_data.chunks_exact(3).zip(buf.chunks_exact_mut(4))
-                                        {
-                                            dst[0] = src[0];
-                                            dst[1] = src[1];
-                                            dst[2] = src[2];
-                                            dst[3] = 255;
-                                        }
-
-                                        buf
-                                    }
-                                    VideoFormat::RGBA => frame_data.to_vec(),
-                                    VideoFormat::RGBx => frame_data.to_vec(),
-                                    VideoFormat::BGRx => {
-                                        let mut buf = frame_data.to_vec();
-                                        for src in buf.chunks_exact_mut(4) {
-                                            src.swap(0, 2);
-                                        }
-
-                                        buf
-                                    }
-                                    _ => {
-                                        log::error!(
-                                            "Unsupported format: {:?}",
-                                            user_data.format.format()
-                                        );
-                                        return;
-                                    }
-                                };
-
-                                let _ = sender.send(Frame::new(size.width, size.height, buffer));
-                            }
+                            let buffer = match data_to_rgba(data, &user_data.format) {
+                                Some(Ok(buffer)) => buffer,
+                                Some(Err(err)) => {
+                                    log::error!("{}", err);
+                                    return;
+                                }
+                                None => return,
+                            };
+
+                            let _ = sender.send(Frame::new(size.width, size.height, buffer));
                         }
                     }
                 })
                 .register()?;
 
-            let obj = pod::object!(
-                SpaTypes::ObjectParamFormat,
-                ParamType::EnumFormat,
-                pod::property!(FormatProperties::MediaType, Id, MediaType::Video),
-                pod::property!(FormatProperties::MediaSubtype, Id, MediaSubtype::Raw),
-                pod::property!(
-                    FormatProperties::VideoFormat,
-                    Choice,
-                    Enum,
-                    Id,
-                    VideoFormat::RGB,
-                    VideoFormat::RGBA,
-                    VideoFormat::RGBx,
-                    VideoFormat::BGRx,
-                    // VideoFormat::YUY2,
-                    // VideoFormat::I420,
-                ),
-                pod::property!(
-                    FormatProperties::VideoSize,
-                    Choice,
-                    Range,
-                    Rectangle,
-                    Rectangle {
-                        width: 128,
-                        height: 128
-                    },
-                    Rectangle {
-                        width: 1,
-                        height: 1
-                    },
-                    Rectangle {
-                        width: 4096,
-                        height: 4096
-                    }
-                ),
-                pod::property!(
-                    FormatProperties::VideoFramerate,
-                    Choice,
-                    Range,
-                    Fraction,
-                    Fraction { num: 24, denom: 1 },
-                    Fraction { num: 0, denom: 1 },
-                    Fraction {
-                        num: 1000,
-                        denom: 1
-                    }
-                ),
-            );
-            let values =
-                PodSerializer::serialize(Cursor::new(Vec::new()), &pod::Value::Object(obj))
-                    .map_err(XCapError::new)?
-                    .0
-                    .into_inner();
+            let values = format_params()?;
 
             let mut params =
                 [Pod::from_bytes(&values).ok_or(XCapError::new("Failed to create Pod"))?];
@@ -379,3 +510,257 @@ impl WaylandVideoRecorder {
         Ok(())
     }
 }
+
+/// Pull a single frame from the PipeWire node `stream_id` on the remote `fd`, the main loop
+/// runs on the calling thread and quits as soon as the first buffer is in.
+fn pipewire_frame(fd: fd::OwnedFd, stream_id: u32) -> XCapResult<RgbaImage> {
+    pipewire::init();
+
+    let main_loop = MainLoop::new(None)?;
+    let context = Context::new(&main_loop)?;
+    let core = context.connect_fd(fd, None)?;
+
+    let user_data = ListenerUserData {
+        format: Default::default(),
+    };
+
+    let stream = Stream::new(
+        &core,
+        "XCap",
+        properties::properties! {
+            *MEDIA_TYPE => "Video",
+            *MEDIA_CATEGORY => "Capture",
+            *MEDIA_ROLE => "Screen",
+        },
+    )?;
+
+    let frame: Rc<RefCell<Option<XCapResult<RgbaImage>>>> = Rc::new(RefCell::new(None));
+
+    let _listener = stream
+        .add_local_listener_with_user_data(user_data)
+        .param_changed(on_param_changed)
+        .state_changed({
+            let main_loop = main_loop.clone();
+            let frame = frame.clone();
+            move |_, _, _, state| {
+                if let StreamState::Error(err) = state {
+                    *frame.borrow_mut() = Some(Err(XCapError::new(err)));
+                    main_loop.quit();
+                }
+            }
+        })
+        .process({
+            let main_loop = main_loop.clone();
+            let frame = frame.clone();
+            move |stream, user_data| {
+                if frame.borrow().is_some() {
+                    return;
+                }
+
+                let Some(mut buffer) = stream.dequeue_buffer() else {
+                    return;
+                };
+                let Some(result) = buffer
+                    .datas_mut()
+                    .first_mut()
+                    .and_then(|data| data_to_rgba(data, &user_data.format))
+                else {
+                    return;
+                };
+
+                let size = user_data.format.size();
+                let result = result.and_then(|buf| {
+                    RgbaImage::from_raw(size.width, size.height, buf).ok_or_else(|| {
+                        XCapError::ImageConversion("RgbaImage::from_raw failed".to_string())
+                    })
+                });
+
+                *frame.borrow_mut() = Some(result);
+                main_loop.quit();
+            }
+        })
+        .register()?;
+
+    let values = format_params()?;
+    let mut params = [Pod::from_bytes(&values).ok_or(XCapError::new("Failed to create Pod"))?];
+
+    stream.connect(
+        Direction::Input,
+        Some(stream_id),
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    main_loop.run();
+
+    frame
+        .take()
+        .unwrap_or_else(|| Err(XCapError::new("PipeWire stream ended without a frame")))
+}
+
+/// ScreenCast session [`screencast_capture`] keeps across captures.
+struct ScreencastSession {
+    session: OwnedObjectPath,
+    streams: Vec<(u32, ScreenCastStartStream)>,
+}
+
+/// The screenshot session, and the restore token of the last one started. Portals prompt
+/// for every new session, a session that stopped working is started again from the token.
+struct ScreencastState {
+    session: Option<ScreencastSession>,
+    restore_token: Option<String>,
+}
+
+static SCREENCAST_STATE: Mutex<ScreencastState> = Mutex::new(ScreencastState {
+    session: None,
+    restore_token: None,
+});
+
+/// Start a session casting the monitors the user picks, or restoring the selection of
+/// `restore_token`. Returns the session and the token to restore it from next time.
+fn start_screencast_session(
+    screen_cast: &ScreenCast<'_>,
+    restore_token: Option<&str>,
+) -> XCapResult<(ScreencastSession, Option<String>)> {
+    let session = screen_cast.create_session()?;
+
+    let response = screen_cast
+        .select_sources(
+            &session,
+            SelectSources {
+                multiple: true,
+                persist: true,
+                restore_token,
+            },
+        )
+        .and_then(|_| screen_cast.start(&session))
+        .and_then(|response| match response.streams {
+            Some(streams) if !streams.is_empty() => Ok((streams, response.restore_token)),
+            _ => Err(XCapError::new("Stream ID not found")),
+        });
+
+    match response {
+        Ok((streams, restore_token)) => Ok((ScreencastSession { session, streams }, restore_token)),
+        Err(err) => {
+            if let Err(err) = screen_cast.close(&session) {
+                log::debug!("closing the screencast session failed {}", err);
+            }
+
+            Err(err)
+        }
+    }
+}
+
+/// The stream whose logical geometry contains the region. Streams without a position are
+/// taken to start at the desktop origin, streams without a size contain nothing.
+fn find_stream(
+    streams: &[(u32, ScreenCastStartStream)],
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+) -> Option<&(u32, ScreenCastStartStream)> {
+    streams.iter().find(|(_, stream)| {
+        let (stream_x, stream_y) = stream.position.unwrap_or((0, 0));
+        let Some((stream_width, stream_height)) = stream.size else {
+            return false;
+        };
+        let (stream_x, stream_y) = (stream_x as i64, stream_y as i64);
+
+        x >= stream_x
+            && y >= stream_y
+            && x + width <= stream_x + stream_width as i64
+            && y + height <= stream_y + stream_height as i64
+    })
+}
+
+/// Read one frame of the session's stream containing the region and crop the region out.
+fn capture_session_region(
+    screen_cast: &ScreenCast<'_>,
+    session: &ScreencastSession,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+) -> XCapResult<RgbaImage> {
+    let (x, y, width, height) = (x as i64, y as i64, width as i64, height as i64);
+    let (stream_id, stream) =
+        find_stream(&session.streams, x, y, width, height).ok_or_else(|| {
+            XCapError::InvalidCaptureRegion(format!(
+                "Region ({}, {}, {}, {}) is outside every screencast stream",
+                x, y, width, height
+            ))
+        })?;
+
+    let fd = screen_cast.open_pipe_wire_remote(&session.session)?;
+    let frame = pipewire_frame(fd, *stream_id)?;
+
+    // The stream reports its logical geometry, the frame comes in physical pixels
+    let (stream_x, stream_y) = stream.position.unwrap_or((0, 0));
+    let (stream_width, _) = stream.size.unwrap_or((frame.width() as i32, 0));
+    let scale = frame.width() as f64 / stream_width.max(1) as f64;
+
+    let left = ((x - stream_x as i64) as f64 * scale).round() as i64;
+    let top = ((y - stream_y as i64) as f64 * scale).round() as i64;
+    let crop_width = (width as f64 * scale).round() as i64;
+    let crop_height = (height as f64 * scale).round() as i64;
+
+    if left < 0
+        || top < 0
+        || left + crop_width > frame.width() as i64
+        || top + crop_height > frame.height() as i64
+    {
+        return Err(XCapError::InvalidCaptureRegion(format!(
+            "Region ({}, {}, {}, {}) is outside the screencast stream",
+            x, y, width, height
+        )));
+    }
+
+    Ok(imageops::crop_imm(
+        &frame,
+        left as u32,
+        top as u32,
+        crop_width as u32,
+        crop_height as u32,
+    )
+    .to_image())
+}
+
+/// Capture a region through the ScreenCast portal and PipeWire, for compositors that only
+/// offer screen content that way. The region, in logical desktop coordinates, has to lie
+/// inside one of the monitors the user picked.
+///
+/// The session is kept for later captures, so only the first one asks the user to pick
+/// monitors. Should it stop working it is started again from the portal's restore token,
+/// which doesn't prompt on portals that support persistence.
+pub fn screencast_capture(x: i32, y: i32, width: i32, height: i32) -> XCapResult<RgbaImage> {
+    let screen_cast = ScreenCast::new()?;
+    let mut state = SCREENCAST_STATE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+
+    let session = match state.session.take() {
+        Some(session) => session,
+        None => {
+            let (session, restore_token) =
+                start_screencast_session(&screen_cast, state.restore_token.as_deref())?;
+            state.restore_token = restore_token;
+
+            session
+        }
+    };
+
+    let result = capture_session_region(&screen_cast, &session, x, y, width, height);
+
+    match &result {
+        // The session itself is fine, only the region didn't fit
+        Ok(_) | Err(XCapError::InvalidCaptureRegion(_)) => state.session = Some(session),
+        Err(_) => {
+            if let Err(err) = screen_cast.close(&session.session) {
+                log::debug!("closing the screencast session failed {}", err);
+            }
+        }
+    }
+
+    result
+}
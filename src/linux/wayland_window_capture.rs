@@ -0,0 +1,373 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::fd::{AsFd, FromRawFd, OwnedFd},
+};
+
+use image::RgbaImage;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum, delegate_noop,
+    event_created_child,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_registry::WlRegistry,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+};
+use wayland_protocols::ext::{
+    foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+        ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+    },
+    image_capture_source::v1::client::{
+        ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+        ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+    },
+    image_copy_capture::v1::client::{
+        ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1},
+        ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1},
+        ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+    },
+};
+
+use crate::error::{XCapError, XCapResult};
+
+use super::xorg_capture::buffer_len;
+
+#[derive(Debug)]
+struct Toplevel {
+    handle: ExtForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+    closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct SessionConstraints {
+    buffer_size: Option<(u32, u32)>,
+    shm_formats: Vec<wl_shm::Format>,
+    done: bool,
+    stopped: bool,
+}
+
+#[derive(Debug, Default)]
+struct CaptureState {
+    toplevels: Vec<Toplevel>,
+    session: SessionConstraints,
+    frame: Option<Result<(), WEnum<ext_image_copy_capture_frame_v1::FailureReason>>>,
+}
+
+impl CaptureState {
+    /// Toplevel with the given title, preferring the one whose app id matches as well when
+    /// several windows share a title. XWayland app ids are usually the `WM_CLASS` class.
+    fn find_toplevel(&self, title: &str, app_id: &str) -> Option<&Toplevel> {
+        let mut candidates = self
+            .toplevels
+            .iter()
+            .filter(|toplevel| !toplevel.closed && toplevel.title == title);
+
+        let first = candidates.next()?;
+        if first.app_id.eq_ignore_ascii_case(app_id) {
+            return Some(first);
+        }
+
+        candidates
+            .find(|toplevel| toplevel.app_id.eq_ignore_ascii_case(app_id))
+            .or(Some(first))
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for CaptureState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push(Toplevel {
+                handle: toplevel,
+                title: String::new(),
+                app_id: String::new(),
+                closed: false,
+            });
+        }
+    }
+
+    event_created_child!(CaptureState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        proxy: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let Some(toplevel) = state
+            .toplevels
+            .iter_mut()
+            .find(|toplevel| &toplevel.handle == proxy)
+        else {
+            return;
+        };
+
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            ext_foreign_toplevel_handle_v1::Event::Closed => toplevel.closed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                state.session.buffer_size = Some((width, height));
+            }
+            ext_image_copy_capture_session_v1::Event::ShmFormat {
+                format: WEnum::Value(format),
+            } => state.session.shm_formats.push(format),
+            ext_image_copy_capture_session_v1::Event::Done => state.session.done = true,
+            ext_image_copy_capture_session_v1::Event::Stopped => state.session.stopped = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for CaptureState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Ready => state.frame = Some(Ok(())),
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                state.frame = Some(Err(reason))
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(CaptureState: ignore WlShm);
+delegate_noop!(CaptureState: ignore WlShmPool);
+delegate_noop!(CaptureState: ignore WlBuffer);
+delegate_noop!(CaptureState: ExtForeignToplevelImageCaptureSourceManagerV1);
+delegate_noop!(CaptureState: ExtImageCaptureSourceV1);
+delegate_noop!(CaptureState: ExtImageCopyCaptureManagerV1);
+
+fn bind_global<I>(globals: &GlobalList, qh: &QueueHandle<CaptureState>) -> XCapResult<I>
+where
+    I: Proxy + 'static,
+    CaptureState: Dispatch<I, ()>,
+{
+    globals.bind::<I, _, _>(qh, 1..=1, ()).map_err(|err| {
+        XCapError::Unsupported(format!(
+            "Window capture on Wayland requires {}: {}",
+            I::interface().name,
+            err
+        ))
+    })
+}
+
+fn dispatch_until<F>(
+    event_queue: &mut EventQueue<CaptureState>,
+    state: &mut CaptureState,
+    is_done: F,
+) -> XCapResult<()>
+where
+    F: Fn(&CaptureState) -> bool,
+{
+    while !is_done(state) {
+        event_queue
+            .blocking_dispatch(state)
+            .map_err(XCapError::new)?;
+    }
+
+    Ok(())
+}
+
+/// Anonymous file shared with the compositor as the `wl_shm` pool backing the frame.
+fn create_shm_file(size: usize) -> XCapResult<File> {
+    let fd = unsafe { libc::memfd_create(c"xcap-window".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    file.set_len(size as u64)?;
+
+    Ok(file)
+}
+
+/// Capture a single toplevel through `ext-image-copy-capture-v1`, identified by its title.
+/// Fails with [`XCapError::Unsupported`] when the compositor lacks any of the protocols.
+pub fn wayland_capture_window(title: &str, app_id: &str) -> XCapResult<RgbaImage> {
+    let conn = Connection::connect_to_env().map_err(|err| {
+        XCapError::Unsupported(format!("Wayland connection unavailable: {}", err))
+    })?;
+    let (globals, mut event_queue) =
+        registry_queue_init::<CaptureState>(&conn).map_err(XCapError::new)?;
+    let qh = event_queue.handle();
+
+    let toplevel_list = bind_global::<ExtForeignToplevelListV1>(&globals, &qh)?;
+    let source_manager =
+        bind_global::<ExtForeignToplevelImageCaptureSourceManagerV1>(&globals, &qh)?;
+    let copy_manager = bind_global::<ExtImageCopyCaptureManagerV1>(&globals, &qh)?;
+    let shm = bind_global::<WlShm>(&globals, &qh)?;
+
+    let mut state = CaptureState::default();
+    // All current toplevels and their initial state are sent right after binding the list
+    event_queue.roundtrip(&mut state).map_err(XCapError::new)?;
+    toplevel_list.stop();
+
+    let handle = state
+        .find_toplevel(title, app_id)
+        .map(|toplevel| toplevel.handle.clone())
+        .ok_or_else(|| XCapError::new(format!("Not found Wayland toplevel {:?}", title)))?;
+
+    let source = source_manager.create_source(&handle, &qh, ());
+    let session = copy_manager.create_session(
+        &source,
+        ext_image_copy_capture_manager_v1::Options::empty(),
+        &qh,
+        (),
+    );
+
+    let result = capture_session(&mut event_queue, &mut state, &shm, &session, &qh);
+
+    session.destroy();
+    source.destroy();
+    for toplevel in &state.toplevels {
+        toplevel.handle.destroy();
+    }
+    copy_manager.destroy();
+    source_manager.destroy();
+    toplevel_list.destroy();
+    let _ = conn.flush();
+
+    result
+}
+
+fn capture_session(
+    event_queue: &mut EventQueue<CaptureState>,
+    state: &mut CaptureState,
+    shm: &WlShm,
+    session: &ExtImageCopyCaptureSessionV1,
+    qh: &QueueHandle<CaptureState>,
+) -> XCapResult<RgbaImage> {
+    dispatch_until(event_queue, state, |state| {
+        state.session.done || state.session.stopped
+    })?;
+
+    if state.session.stopped {
+        return Err(XCapError::new("Wayland capture session stopped"));
+    }
+
+    let (width, height) = state
+        .session
+        .buffer_size
+        .ok_or(XCapError::new("Wayland capture session has no buffer size"))?;
+
+    // Little endian ARGB/XRGB is `B, G, R, A` in memory, ABGR/XBGR is `R, G, B, A`
+    let (format, is_bgra) = state
+        .session
+        .shm_formats
+        .iter()
+        .find_map(|format| match format {
+            wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => Some((*format, true)),
+            wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => Some((*format, false)),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            XCapError::Unsupported(format!(
+                "Wayland capture session offers no 8 bit RGB shm format: {:?}",
+                state.session.shm_formats
+            ))
+        })?;
+    let has_alpha = matches!(format, wl_shm::Format::Argb8888 | wl_shm::Format::Abgr8888);
+
+    let size = buffer_len(width, height, 4)?;
+    let pool_size = i32::try_from(size).map_err(XCapError::new)?;
+    let mut file = create_shm_file(size)?;
+
+    let pool = shm.create_pool(file.as_fd(), pool_size, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        width as i32 * 4,
+        format,
+        qh,
+        (),
+    );
+
+    let frame = session.create_frame(qh, ());
+    frame.attach_buffer(&buffer);
+    frame.damage_buffer(0, 0, width as i32, height as i32);
+    frame.capture();
+
+    let result = dispatch_until(event_queue, state, |state| state.frame.is_some());
+
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+    result?;
+
+    if let Some(Err(reason)) = state.frame.take() {
+        return Err(XCapError::new(format!(
+            "Wayland frame capture failed: {:?}",
+            reason
+        )));
+    }
+
+    let mut rgba = vec![0; size];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut rgba)?;
+
+    for pixel in rgba.chunks_exact_mut(4) {
+        if is_bgra {
+            pixel.swap(0, 2);
+        }
+        if !has_alpha {
+            pixel[3] = 255;
+        }
+    }
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
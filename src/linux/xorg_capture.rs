@@ -1,28 +1,333 @@
-use image::RgbaImage;
+use std::{
+    borrow::{Borrow, Cow},
+    collections::{HashMap, HashSet},
+    io,
+    ops::{Deref, DerefMut},
+    os::fd::AsRawFd,
+    path::Path,
+    ptr, slice,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use image::{ColorType, DynamicImage, GrayImage, RgbImage, Rgba32FImage, RgbaImage};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use xcb::{
-    Connection,
-    x::{Drawable, GetImage, ImageFormat, ImageOrder, Window},
+    Connection, CookieWithReplyChecked, Extension, SpecialEvent, Xid, composite, present, shm,
+    x::{
+        Colormap, Drawable, FreePixmap, GetGeometry, GetImage, GetImageReply, GetWindowAttributes,
+        ImageFormat, ImageOrder, QueryColors, Setup, VisualClass, Visualid, Visualtype, Window,
+    },
+    xfixes,
 };
 
 use crate::error::{XCapError, XCapResult};
 
+use super::{
+    float::{Standardization, to_f32},
+    mapped_file::MappedFile,
+    nv12::{Nv12Image, YuvMatrix, to_nv12},
+    planar::{PlanarImage, to_planar},
+    swizzle::bgrx_to_rgba,
+    utils::{XorgCaptureOptions, connect_with_retry},
+};
+
+/// Vec can't hold more than `isize::MAX` bytes
+const MAX_BUFFER_LEN: u64 = isize::MAX as u64;
+
+/// `GetImage` plane mask selecting every bit of the pixel
+const ALL_PLANES: u32 = u32::MAX;
+
+/// Captures [`xorg_capture_window`] makes before giving up on a window resized every time
+const RESIZE_ATTEMPTS: usize = 3;
+
+/// Applications can store into writable colormaps at any time, their cached palettes are read
+/// again once they are this old
+const PALETTE_MAX_AGE: Duration = Duration::from_secs(1);
+
+/// Set once the missing Present extension was logged, so vsync doesn't warn on every capture
+static VSYNC_UNSUPPORTED_LOGGED: AtomicBool = AtomicBool::new(false);
+
+/// Approximate size of one band of [`xorg_capture_to_mmap`], bounding the memory it needs
+const MMAP_BAND_LEN: usize = 16 << 20;
+
+/// Size in bytes of a `width` x `height` buffer, checked so huge virtual desktops can't wrap
+/// around to a short allocation.
+pub(crate) fn buffer_len(width: u32, height: u32, channels: usize) -> XCapResult<usize> {
+    (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|len| len.checked_mul(channels as u64))
+        .filter(|&len| len <= MAX_BUFFER_LEN)
+        .and_then(|len| usize::try_from(len).ok())
+        .ok_or(XCapError::RegionTooLarge { width, height })
+}
+
+/// Smallest integer factor that brings `width` x `height` within `max_dimensions`.
+pub(crate) fn fit_factor(width: u32, height: u32, (max_width, max_height): (u32, u32)) -> u32 {
+    width
+        .div_ceil(max_width.max(1))
+        .max(height.div_ceil(max_height.max(1)))
+        .max(1)
+}
+
+/// Position of a color channel inside a pixel value, derived from a visual mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    fn from_mask(mask: u32) -> Channel {
+        if mask == 0 {
+            return Channel { shift: 0, bits: 0 };
+        }
+
+        let shift = mask.trailing_zeros();
+
+        Channel {
+            shift,
+            bits: (mask >> shift).count_ones(),
+        }
+    }
+
+    /// Extract the channel from `pixel` and scale it to 8 bits.
+    fn extract(&self, pixel: u32) -> u8 {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let max = (1u64 << self.bits) - 1;
+        let value = (pixel as u64 >> self.shift) & max;
+
+        // Wider channels (e.g. 10 bits at depth 30) keep their most significant bits
+        if self.bits >= 8 {
+            (value >> (self.bits - 8)) as u8
+        } else {
+            (value * 255 / max) as u8
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        (((1u64 << self.bits) - 1) << self.shift) as u32
+    }
+
+    /// Byte offset of the channel inside a `bytes_per_pixel` wide pixel, if it
+    /// occupies exactly one whole byte.
+    fn byte_offset(&self, bytes_per_pixel: u32, byte_order: ImageOrder) -> Option<usize> {
+        if self.bits != 8 || !self.shift.is_multiple_of(8) {
+            return None;
+        }
+
+        let offset = self.shift / 8;
+
+        if byte_order == ImageOrder::LsbFirst {
+            Some(offset as usize)
+        } else {
+            Some((bytes_per_pixel - 1 - offset) as usize)
+        }
+    }
+}
+
+/// RGB of every entry of an 8 bits colormap.
+type Palette = [(u8, u8, u8); 256];
+
+/// Layout of the pixels returned by `GetImage`.
+#[derive(Debug, Clone, Copy)]
+struct PixelFormat {
+    depth: u8,
+    bits_per_pixel: u32,
+    byte_order: ImageOrder,
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+    /// Only depth 32 has an alpha plane, made of the bits the color masks leave over
+    alpha: Option<Channel>,
+    /// Every row is padded to a multiple of this many bits
+    scanline_pad: u32,
+    /// Colormap of PseudoColor and other indexed visuals, whose pixels are palette indices
+    palette: Option<Palette>,
+}
+
+impl PixelFormat {
+    fn new(
+        depth: u8,
+        bits_per_pixel: u32,
+        byte_order: ImageOrder,
+        visual: Option<&Visualtype>,
+    ) -> PixelFormat {
+        // Without a visual assume the common RGB555, RGB565, `0x00RRGGBB` or `X2R10G10B10` layout
+        let default_masks = match depth {
+            15 => (0x7c00, 0x3e0, 0x1f),
+            16 => (0xf800, 0x7e0, 0x1f),
+            30 => (0x3ff00000, 0xffc00, 0x3ff),
+            _ => (0xff0000, 0xff00, 0xff),
+        };
+
+        let masks = visual
+            .map(|visual| (visual.red_mask(), visual.green_mask(), visual.blue_mask()))
+            .unwrap_or(default_masks);
+
+        PixelFormat::from_masks(depth, bits_per_pixel, byte_order, masks)
+    }
+
+    fn from_masks(
+        depth: u8,
+        bits_per_pixel: u32,
+        byte_order: ImageOrder,
+        (red_mask, green_mask, blue_mask): (u32, u32, u32),
+    ) -> PixelFormat {
+        let alpha_mask = !(red_mask | green_mask | blue_mask);
+        let alpha = (depth == 32 && alpha_mask != 0).then(|| Channel::from_mask(alpha_mask));
+
+        PixelFormat {
+            depth,
+            bits_per_pixel,
+            byte_order,
+            red: Channel::from_mask(red_mask),
+            green: Channel::from_mask(green_mask),
+            blue: Channel::from_mask(blue_mask),
+            alpha,
+            scanline_pad: 8,
+            palette: None,
+        }
+    }
+
+    /// Take the alpha plane from the visual's depth rather than the reply's. Some drivers
+    /// answer `GetImage` on a depth 32 window with depth 24 data or the other way around,
+    /// the bytes are still laid out per the reply but only an ARGB visual has real alpha.
+    fn with_visual_depth(self, visual_depth: u8) -> PixelFormat {
+        let alpha_mask = !(self.red.mask() | self.green.mask() | self.blue.mask());
+        let has_alpha = visual_depth == 32 && self.bits_per_pixel == 32 && alpha_mask != 0;
+
+        PixelFormat {
+            alpha: has_alpha.then(|| Channel::from_mask(alpha_mask)),
+            ..self
+        }
+    }
+
+    fn with_palette(self, palette: Palette) -> PixelFormat {
+        PixelFormat {
+            palette: Some(palette),
+            ..self
+        }
+    }
+
+    fn with_scanline_pad(self, scanline_pad: u32) -> PixelFormat {
+        PixelFormat {
+            scanline_pad: scanline_pad.max(8),
+            ..self
+        }
+    }
+
+    /// Bytes per row of a `width` pixels wide image, padding included.
+    fn stride(&self, width: u32) -> usize {
+        let pad = self.scanline_pad as usize;
+
+        (width as usize * self.bits_per_pixel as usize).div_ceil(pad) * pad / 8
+    }
+
+    /// Offset of the first byte of pixel `(x, y)`.
+    fn pixel_offset(&self, x: u32, y: u32, width: u32) -> usize {
+        y as usize * self.stride(width) + x as usize * self.bits_per_pixel as usize / 8
+    }
+
+    /// Whether the rows follow each other without padding, as the slice fast paths expect.
+    fn is_packed(&self, width: u32) -> bool {
+        self.stride(width) == width as usize * self.bits_per_pixel as usize / 8
+    }
+
+    /// Size in bytes of a whole `width` x `height` image, padding included.
+    fn image_len(&self, width: u32, height: u32) -> XCapResult<usize> {
+        (self.stride(width) as u64)
+            .checked_mul(height as u64)
+            .filter(|&len| len <= MAX_BUFFER_LEN)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or(XCapError::RegionTooLarge { width, height })
+    }
+
+    fn bytes_per_pixel(&self) -> u32 {
+        self.bits_per_pixel / 8
+    }
+
+    /// Byte offsets of the red, green and blue channels for 32 bits pixels
+    /// where every channel is a whole byte.
+    fn byte_offsets(&self) -> Option<[usize; 3]> {
+        if self.bits_per_pixel != 32 {
+            return None;
+        }
+
+        let bytes_per_pixel = self.bytes_per_pixel();
+
+        Some([
+            self.red.byte_offset(bytes_per_pixel, self.byte_order)?,
+            self.green.byte_offset(bytes_per_pixel, self.byte_order)?,
+            self.blue.byte_offset(bytes_per_pixel, self.byte_order)?,
+        ])
+    }
+
+    /// Assemble the pixel value from its bytes.
+    fn read_pixel(&self, bytes: &[u8]) -> u32 {
+        if self.byte_order == ImageOrder::LsbFirst {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |pixel, &byte| (pixel << 8) | byte as u32)
+        } else {
+            bytes
+                .iter()
+                .fold(0, |pixel, &byte| (pixel << 8) | byte as u32)
+        }
+    }
+}
+
+/// Find the visual describing the reply together with its depth, falling back to the first
+/// visual of the reply's depth when the reply doesn't name one. The named visual is looked
+/// up at every depth, it doesn't always have the depth the reply reports.
+fn find_visual(setup: &Setup, depth: u8, visual_id: Visualid) -> Option<(&Visualtype, u8)> {
+    let visuals: Vec<(&Visualtype, u8)> = setup
+        .roots()
+        .flat_map(|screen| screen.allowed_depths())
+        .flat_map(|allowed_depth| {
+            allowed_depth
+                .visuals()
+                .iter()
+                .map(move |visual| (visual, allowed_depth.depth()))
+        })
+        .collect();
+
+    visuals
+        .iter()
+        .find(|(visual, _)| visual.visual_id() == visual_id)
+        .or_else(|| {
+            visuals
+                .iter()
+                .find(|&&(_, visual_depth)| visual_depth == depth)
+        })
+        .copied()
+}
+
 fn get_pixel8_rgba(
     bytes: &[u8],
     x: u32,
     y: u32,
     width: u32,
-    bits_per_pixel: u32,
-    bit_order: ImageOrder,
+    format: &PixelFormat,
 ) -> (u8, u8, u8, u8) {
-    let index = ((y * width + x) * bits_per_pixel / 8) as usize;
+    let index = format.pixel_offset(x, y, width);
+    let pixel = bytes[index];
 
-    let pixel = if bit_order == ImageOrder::LsbFirst {
-        bytes[index]
-    } else {
-        bytes[index] & (7 << 4) | (bytes[index] >> 4)
-    };
+    if let Some(palette) = &format.palette {
+        let (r, g, b) = palette[pixel as usize];
+        return (r, g, b, 255);
+    }
 
-    let r = (pixel >> 6) as f32 / 3.0 * 255.0;
+    // TrueColor 8bpp pixels are a single byte laid out as RRRGGGBB, byte order doesn't apply
+    let r = (pixel >> 5) as f32 / 7.0 * 255.0;
     let g = ((pixel >> 2) & 7) as f32 / 7.0 * 255.0;
     let b = (pixel & 3) as f32 / 3.0 * 255.0;
 
@@ -34,96 +339,2774 @@ fn get_pixel16_rgba(
     x: u32,
     y: u32,
     width: u32,
-    bits_per_pixel: u32,
-    bit_order: ImageOrder,
+    format: &PixelFormat,
 ) -> (u8, u8, u8, u8) {
-    let index = ((y * width + x) * bits_per_pixel / 8) as usize;
+    let index = format.pixel_offset(x, y, width);
 
-    let pixel = if bit_order == ImageOrder::LsbFirst {
+    let pixel = if format.byte_order == ImageOrder::LsbFirst {
         bytes[index] as u16 | ((bytes[index + 1] as u16) << 8)
     } else {
         ((bytes[index] as u16) << 8) | bytes[index + 1] as u16
     };
 
-    let r = (pixel >> 11) as f32 / 31.0 * 255.0;
-    let g = ((pixel >> 5) & 63) as f32 / 63.0 * 255.0;
-    let b = (pixel & 31) as f32 / 31.0 * 255.0;
+    // RGB555 visuals (depth 15) leave the top bit as padding and give green only 5 bits
+    let (r, g, b) = if format.green.bits == 5 {
+        (
+            ((pixel >> 10) & 31) as f32 / 31.0 * 255.0,
+            ((pixel >> 5) & 31) as f32 / 31.0 * 255.0,
+            (pixel & 31) as f32 / 31.0 * 255.0,
+        )
+    } else {
+        (
+            (pixel >> 11) as f32 / 31.0 * 255.0,
+            ((pixel >> 5) & 63) as f32 / 63.0 * 255.0,
+            (pixel & 31) as f32 / 31.0 * 255.0,
+        )
+    };
 
     (r as u8, g as u8, b as u8, 255)
 }
 
+/// Also used for depth 30, whose 10 bits channels are located by the visual masks
+/// inside the 32 bits word.
 fn get_pixel24_32_rgba(
     bytes: &[u8],
     x: u32,
     y: u32,
     width: u32,
-    bits_per_pixel: u32,
-    bit_order: ImageOrder,
+    format: &PixelFormat,
 ) -> (u8, u8, u8, u8) {
-    let index = ((y * width + x) * bits_per_pixel / 8) as usize;
+    let bytes_per_pixel = format.bytes_per_pixel() as usize;
+    let index = format.pixel_offset(x, y, width);
+    let pixel = format.read_pixel(&bytes[index..index + bytes_per_pixel]);
 
-    if bit_order == ImageOrder::LsbFirst {
-        (bytes[index + 2], bytes[index + 1], bytes[index], 255)
-    } else {
-        (bytes[index], bytes[index + 1], bytes[index + 2], 255)
+    (
+        format.red.extract(pixel),
+        format.green.extract(pixel),
+        format.blue.extract(pixel),
+        format.alpha.map_or(255, |alpha| alpha.extract(pixel)),
+    )
+}
+
+type PixelReader = fn(&[u8], u32, u32, u32, &PixelFormat) -> (u8, u8, u8, u8);
+
+fn get_pixel_reader(depth: u8) -> XCapResult<PixelReader> {
+    match depth {
+        8 => Ok(get_pixel8_rgba),
+        15 => Ok(get_pixel16_rgba),
+        16 => Ok(get_pixel16_rgba),
+        24 => Ok(get_pixel24_32_rgba),
+        30 => Ok(get_pixel24_32_rgba),
+        32 => Ok(get_pixel24_32_rgba),
+        depth @ (1 | 4) => Err(XCapError::UnsupportedMonochromeDepth(depth)),
+        depth => Err(XCapError::UnsupportedDepth(depth)),
     }
 }
 
-pub fn xorg_capture(
-    window: Window,
+/// Fail early for depths the decoder can't handle, before anything is requested.
+pub(crate) fn check_depth(depth: u8) -> XCapResult<()> {
+    get_pixel_reader(depth).map(|_| ())
+}
+
+// The fast paths below view both buffers as fixed size pixel arrays, so every byte access
+// uses a constant index into a `[u8; N]` and the loops carry no per-byte bounds checks.
+// `check_image_len` validated the source length up front and `buf` is sized to exactly
+// `width * height * channels`, the trailing remainders `as_chunks` returns are always empty.
+
+/// Depth 24/32 pixels stored as 4 bytes in `R, G, B, X` order, only the alpha
+/// byte needs to be fixed up after the copy, unless it is a real alpha channel.
+fn rgbx_to_rgba(src: &[u8], rgba: &mut [u8], keep_alpha: bool) {
+    rgba.copy_from_slice(&src[..rgba.len()]);
+    if keep_alpha {
+        return;
+    }
+
+    for dst in rgba.as_chunks_mut::<4>().0 {
+        dst[3] = 255;
+    }
+}
+
+/// Depth 24/32 pixels stored as 4 bytes in `B, G, R, X` order to packed RGB,
+/// the padding or alpha byte is dropped.
+fn bgrx_to_rgb(src: &[u8], rgb: &mut [u8]) {
+    let (src, _) = src.as_chunks::<4>();
+    let (rgb, _) = rgb.as_chunks_mut::<3>();
+
+    for (dst, &[b, g, r, _]) in rgb.iter_mut().zip(src) {
+        *dst = [r, g, b];
+    }
+}
+
+/// Depth 24/32 pixels stored as 4 bytes in `R, G, B, X` order to packed RGB.
+fn rgbx_to_rgb(src: &[u8], rgb: &mut [u8]) {
+    let (src, _) = src.as_chunks::<4>();
+    let (rgb, _) = rgb.as_chunks_mut::<3>();
+
+    for (dst, &[r, g, b, _]) in rgb.iter_mut().zip(src) {
+        *dst = [r, g, b];
+    }
+}
+
+/// A System V shared memory segment used as the MIT-SHM `GetImage` target.
+struct ShmImage {
+    shmid: i32,
+    addr: *mut u8,
+    capacity: usize,
+    size: usize,
+}
+
+impl ShmImage {
+    fn new(capacity: usize) -> XCapResult<ShmImage> {
+        let shmid = unsafe { libc::shmget(libc::IPC_PRIVATE, capacity, libc::IPC_CREAT | 0o600) };
+        if shmid == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let addr = unsafe { libc::shmat(shmid, ptr::null(), 0) };
+        if addr as isize == -1 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut()) };
+            return Err(err.into());
+        }
+
+        Ok(ShmImage {
+            shmid,
+            addr: addr as *mut u8,
+            capacity,
+            size: 0,
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr, self.size) }
+    }
+}
+
+impl Drop for ShmImage {
+    fn drop(&mut self) {
+        // The X server keeps its own attachment until it detaches or the connection closes
+        unsafe {
+            libc::shmctl(self.shmid, libc::IPC_RMID, ptr::null_mut());
+            libc::shmdt(self.addr as *const libc::c_void);
+        }
+    }
+}
+
+/// The X server's attachment of a segment, detached again when dropped so a capture that
+/// fails or panics half way doesn't leave the server holding on to it.
+struct ShmAttachment<'a> {
+    conn: &'a Connection,
+    shmseg: shm::Seg,
+}
+
+impl Drop for ShmAttachment<'_> {
+    fn drop(&mut self) {
+        self.conn.send_request(&shm::Detach {
+            shmseg: self.shmseg,
+        });
+    }
+}
+
+/// Pixel data returned by either the core or the MIT-SHM `GetImage` request.
+enum ImageData {
+    Reply(GetImageReply),
+    Shm(ShmImage),
+}
+
+impl ImageData {
+    fn data(&self) -> &[u8] {
+        match self {
+            ImageData::Reply(get_image_reply) => get_image_reply.data(),
+            ImageData::Shm(shm_image) => shm_image.data(),
+        }
+    }
+}
+
+/// `wait_for_reply` bounded by [`XorgCaptureOptions::capture_timeout`], polling the
+/// connection's socket until the reply is in or the deadline passes.
+fn wait_for_reply<C>(conn: &Connection, cookie: C) -> XCapResult<C::Reply>
+where
+    C: CookieWithReplyChecked,
+{
+    let Some(timeout) = XorgCaptureOptions::global().capture_timeout else {
+        return Ok(conn.wait_for_reply(cookie)?);
+    };
+
+    conn.flush()?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(reply) = conn.poll_for_reply(&cookie) {
+            return Ok(reply?);
+        }
+
+        wait_readable(conn, deadline, timeout)?;
+    }
+}
+
+/// Sleep until the connection's socket has data or `deadline` passes, the latter failing
+/// with [`XCapError::Timeout`] for the configured `timeout`.
+fn wait_readable(conn: &Connection, deadline: Instant, timeout: Duration) -> XCapResult<()> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(XCapError::Timeout(timeout));
+    }
+
+    let mut pollfd = libc::pollfd {
+        fd: conn.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = remaining.as_millis().clamp(1, i32::MAX as u128) as i32;
+
+    if unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait on the Present queue `special_event` for the `CompleteNotify` of `serial`, bounded
+/// by [`XorgCaptureOptions::capture_timeout`] like [`wait_for_reply`].
+fn wait_for_msc(conn: &Connection, special_event: &SpecialEvent, serial: u32) -> XCapResult<()> {
+    let is_complete = |event: xcb::Event| {
+        matches!(
+            event,
+            xcb::Event::Present(present::Event::CompleteNotify(event)) if event.serial() == serial
+        )
+    };
+
+    let Some(timeout) = XorgCaptureOptions::global().capture_timeout else {
+        while !is_complete(conn.wait_for_special_event2(special_event)?) {}
+        return Ok(());
+    };
+
+    conn.flush()?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        while let Some(event) = conn.poll_for_special_event2(special_event)? {
+            if is_complete(event) {
+                return Ok(());
+            }
+        }
+
+        wait_readable(conn, deadline, timeout)?;
+    }
+}
+
+fn shm_get_image(
+    conn: &Connection,
+    drawable: Drawable,
     x: i32,
     y: i32,
     width: u32,
     height: u32,
-) -> XCapResult<RgbaImage> {
-    let (conn, _) = Connection::connect(None)?;
+    plane_mask: u32,
+) -> XCapResult<(ImageData, u8, Visualid)> {
+    // 32 bits per pixel is the widest supported format, rows never need more than that
+    let mut shm_image = ShmImage::new(buffer_len(width, height, 4)?)?;
 
-    let setup = conn.get_setup();
+    let shmseg: shm::Seg = conn.generate_id();
+    conn.send_and_check_request(&shm::Attach {
+        shmseg,
+        shmid: shm_image.shmid as u32,
+        read_only: false,
+    })
+    .map_err(xcb::Error::from)?;
+    let attachment = ShmAttachment { conn, shmseg };
+
+    let shm_get_image_cookie = conn.send_request(&shm::GetImage {
+        drawable,
+        x: x as i16,
+        y: y as i16,
+        width: width as u16,
+        height: height as u16,
+        plane_mask,
+        format: ImageFormat::ZPixmap as u8,
+        shmseg,
+        offset: 0,
+    });
+    let shm_get_image_reply = wait_for_reply(conn, shm_get_image_cookie);
+    drop(attachment);
+
+    let shm_get_image_reply = shm_get_image_reply?;
+    shm_image.size = (shm_get_image_reply.size() as usize).min(shm_image.capacity);
+
+    Ok((
+        ImageData::Shm(shm_image),
+        shm_get_image_reply.depth(),
+        shm_get_image_reply.visual(),
+    ))
+}
 
+fn core_get_image(
+    conn: &Connection,
+    drawable: Drawable,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    plane_mask: u32,
+) -> XCapResult<(ImageData, u8, Visualid)> {
     let get_image_cookie = conn.send_request(&GetImage {
         format: ImageFormat::ZPixmap,
-        drawable: Drawable::Window(window),
+        drawable,
         x: x as i16,
         y: y as i16,
         width: width as u16,
         height: height as u16,
-        plane_mask: u32::MAX,
+        plane_mask,
     });
 
-    let get_image_reply = conn.wait_for_reply(get_image_cookie)?;
-    let bytes = get_image_reply.data();
+    let get_image_reply = wait_for_reply(conn, get_image_cookie)?;
     let depth = get_image_reply.depth();
+    let visual = get_image_reply.visual();
+
+    Ok((ImageData::Reply(get_image_reply), depth, visual))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip(conn, drawable),
+        fields(depth = tracing::field::Empty, bytes = tracing::field::Empty)
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+fn request_image(
+    conn: &Connection,
+    has_shm: bool,
+    drawable: Drawable,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    plane_mask: u32,
+) -> XCapResult<(ImageData, u8, Visualid)> {
+    let result = if has_shm {
+        shm_get_image(conn, drawable, x, y, width, height, plane_mask).or_else(|err| match err {
+            // The server is stalled, a core request would only wait just as long again
+            XCapError::Timeout(_) => Err(err),
+            err => {
+                log::debug!("shm_get_image failed {}", err);
+                core_get_image(conn, drawable, x, y, width, height, plane_mask)
+            }
+        })
+    } else {
+        core_get_image(conn, drawable, x, y, width, height, plane_mask)
+    };
+
+    #[cfg(feature = "tracing")]
+    if let Ok((image_data, depth, _)) = &result {
+        let span = tracing::Span::current();
+        span.record("depth", depth);
+        span.record("bytes", image_data.data().len());
+    }
 
+    result
+}
+
+/// Whether pixels of `visual` index a colormap instead of holding the color bits.
+fn is_indexed(visual: &Visualtype) -> bool {
+    matches!(
+        visual.class(),
+        VisualClass::StaticGray
+            | VisualClass::GrayScale
+            | VisualClass::StaticColor
+            | VisualClass::PseudoColor
+    )
+}
+
+/// Colormap of an indexed visual the pixels of a reply are looked up in.
+#[derive(Debug, Clone, Copy)]
+struct IndexedVisual {
+    /// Screen default colormap, for windows that have none
+    default_colormap: Colormap,
+    /// Number of colormap entries, pixels past it are never looked up
+    entries: u16,
+    /// GrayScale and PseudoColor entries can be stored into at any time
+    writable: bool,
+}
+
+/// The colormap layout of the reply's visual when it is indexed, `None` for visuals whose
+/// pixels hold the color bits. Only depth 8 palettes are decoded.
+fn find_indexed_visual(setup: &Setup, depth: u8, visual: Visualid) -> Option<IndexedVisual> {
+    let (visualtype, _) = find_visual(setup, depth, visual)?;
+    if depth != 8 || !is_indexed(visualtype) {
+        return None;
+    }
+
+    let default_colormap = setup
+        .roots()
+        .find(|screen| {
+            screen
+                .allowed_depths()
+                .flat_map(|allowed_depth| allowed_depth.visuals())
+                .any(|item| item.visual_id() == visualtype.visual_id())
+        })?
+        .default_colormap();
+
+    Some(IndexedVisual {
+        default_colormap,
+        entries: visualtype.colormap_entries().min(256),
+        writable: matches!(
+            visualtype.class(),
+            VisualClass::GrayScale | VisualClass::PseudoColor
+        ),
+    })
+}
+
+/// Colormap `window` was created or set with, the reply doesn't name it. Windows without
+/// one, e.g. when it was freed, are shown through the screen's default colormap.
+fn window_colormap(
+    conn: &Connection,
+    window: Window,
+    indexed_visual: &IndexedVisual,
+) -> XCapResult<Colormap> {
+    let colormap =
+        wait_for_reply(conn, conn.send_request(&GetWindowAttributes { window }))?.colormap();
+
+    if colormap.is_none() {
+        Ok(indexed_visual.default_colormap)
+    } else {
+        Ok(colormap)
+    }
+}
+
+/// Read the first `entries` entries of `colormap`, `QueryColors` returns 16 bits per
+/// channel and fails for pixels past the colormap's size.
+fn query_palette(conn: &Connection, colormap: Colormap, entries: u16) -> XCapResult<Palette> {
+    let pixels: Vec<u32> = (0..entries as u32).collect();
+    let query_colors_reply = wait_for_reply(
+        conn,
+        conn.send_request(&QueryColors {
+            cmap: colormap,
+            pixels: &pixels,
+        }),
+    )?;
+
+    let mut palette = [(0, 0, 0); 256];
+    for (entry, color) in palette.iter_mut().zip(query_colors_reply.colors()) {
+        *entry = (
+            (color.red() >> 8) as u8,
+            (color.green() >> 8) as u8,
+            (color.blue() >> 8) as u8,
+        );
+    }
+
+    Ok(palette)
+}
+
+fn resolve_pixel_format(conn: &Connection, depth: u8, visual: Visualid) -> XCapResult<PixelFormat> {
+    let setup = conn.get_setup();
     let pixmap_format = setup
         .pixmap_formats()
         .iter()
         .find(|item| item.depth() == depth)
-        .ok_or(XCapError::new("Not found pixmap format"))?;
-
-    let bits_per_pixel = pixmap_format.bits_per_pixel() as u32;
-    let bit_order = setup.bitmap_format_bit_order();
+        .ok_or(XCapError::PixmapFormatNotFound(depth))?;
 
-    let get_pixel_rgba = match depth {
-        8 => get_pixel8_rgba,
-        16 => get_pixel16_rgba,
-        24 => get_pixel24_32_rgba,
-        32 => get_pixel24_32_rgba,
-        _ => return Err(XCapError::new(format!("Unsupported {} depth", depth))),
+    let (visualtype, visual_depth) = match find_visual(setup, depth, visual) {
+        Some((visualtype, visual_depth)) => (Some(visualtype), visual_depth),
+        None => (None, depth),
     };
 
-    let mut rgba = vec![0u8; (width * height * 4) as usize];
-    for y in 0..height {
-        for x in 0..width {
-            let index = ((y * width + x) * 4) as usize;
-            let (r, g, b, a) = get_pixel_rgba(bytes, x, y, width, bits_per_pixel, bit_order);
+    // ZPixmap rows are padded per the pixmap format and their pixels assembled per the image
+    // byte order, `bitmap_format_scanline_pad` and `bitmap_format_bit_order` only apply to
+    // bitmaps and XYPixmap planes
+    let pixel_format = PixelFormat::new(
+        depth,
+        pixmap_format.bits_per_pixel() as u32,
+        setup.image_byte_order(),
+        visualtype,
+    )
+    .with_scanline_pad(pixmap_format.scanline_pad() as u32)
+    .with_visual_depth(visual_depth);
+
+    Ok(pixel_format)
+}
+
+/// Connection reused across captures, together with the pixel formats already resolved on it.
+///
+/// The cache is keyed by the reply's depth and visual, palettes of indexed visuals by their
+/// colormap. It lives and dies with the connection: when the connection breaks it is
+/// recreated and the cache starts out empty again.
+///
+/// Dropping it closes the socket, shared memory segments never outlive the capture that
+/// created them, so nothing is left behind by a capturer going out of scope.
+pub(crate) struct CaptureConnection<C = Connection> {
+    conn: C,
+    has_shm: bool,
+    has_xfixes: bool,
+    has_composite: bool,
+    /// Present 1.0, needed to wait for vblank
+    has_present: bool,
+    /// Serial of the last `NotifyMsc`, tells its `CompleteNotify` apart from stale ones
+    msc_serial: u32,
+    pixel_formats: HashMap<(u8, Visualid), PixelFormat>,
+    /// Palettes of the colormaps read so far, with when they were read
+    palettes: HashMap<Colormap, (Instant, Palette)>,
+    /// Windows redirected for [`CaptureConnection::get_composite_image`], the server drops
+    /// the redirects when the connection closes
+    redirected: HashSet<Window>,
+    /// A reply timed out and may still arrive, the connection is out of step and retired
+    timed_out: bool,
+}
+
+impl CaptureConnection {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            name = "xorg_connect",
+            fields(has_shm = tracing::field::Empty, has_xfixes = tracing::field::Empty)
+        )
+    )]
+    pub(crate) fn new() -> XCapResult<CaptureConnection> {
+        let (conn, _) = connect_with_retry(&XorgCaptureOptions::global(), || {
+            Connection::connect_with_extensions(
+                None,
+                &[],
+                &[
+                    Extension::Shm,
+                    Extension::XFixes,
+                    Extension::Composite,
+                    Extension::Damage,
+                    Extension::Present,
+                ],
+            )
+        })?;
+        // MIT-SHM is unusable over remote connections, fall back to the core request
+        let has_shm = conn.active_extensions().any(|item| item == Extension::Shm);
+
+        // XFixes requests are rejected until the client announced its version
+        let has_xfixes = conn
+            .active_extensions()
+            .any(|item| item == Extension::XFixes)
+            && conn
+                .wait_for_reply(conn.send_request(&xfixes::QueryVersion {
+                    client_major_version: 4,
+                    client_minor_version: 0,
+                }))
+                .is_ok();
+
+        // NameWindowPixmap needs Composite 0.2
+        let has_composite = conn
+            .active_extensions()
+            .any(|item| item == Extension::Composite)
+            && conn
+                .wait_for_reply(conn.send_request(&composite::QueryVersion {
+                    client_major_version: 0,
+                    client_minor_version: 2,
+                }))
+                .is_ok_and(|reply| (reply.major_version(), reply.minor_version()) >= (0, 2));
+
+        let has_present = conn
+            .active_extensions()
+            .any(|item| item == Extension::Present)
+            && conn
+                .wait_for_reply(conn.send_request(&present::QueryVersion {
+                    major_version: 1,
+                    minor_version: 0,
+                }))
+                .is_ok();
 
-            rgba[index] = r;
-            rgba[index + 1] = g;
-            rgba[index + 2] = b;
-            rgba[index + 3] = a;
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("has_shm", has_shm);
+            span.record("has_xfixes", has_xfixes);
         }
+
+        Ok(CaptureConnection {
+            conn,
+            has_shm,
+            has_xfixes,
+            has_composite,
+            has_present,
+            msc_serial: 0,
+            pixel_formats: HashMap::new(),
+            palettes: HashMap::new(),
+            redirected: HashSet::new(),
+            timed_out: false,
+        })
     }
+}
 
-    RgbaImage::from_raw(width, height, rgba)
-        .ok_or_else(|| XCapError::new("RgbaImage::from_raw failed"))
+impl<'a> CaptureConnection<&'a Connection> {
+    /// Wrap a connection owned by the caller, MIT-SHM is only used when the caller connected
+    /// with that extension. Only Present is probed, and only when vsync is on, the other
+    /// extensions would cost a round trip each for captures that don't use them.
+    pub(crate) fn borrowed(conn: &'a Connection) -> CaptureConnection<&'a Connection> {
+        let has_shm = conn.active_extensions().any(|item| item == Extension::Shm);
+        let has_present = XorgCaptureOptions::global().vsync
+            && conn
+                .active_extensions()
+                .any(|item| item == Extension::Present)
+            && conn
+                .wait_for_reply(conn.send_request(&present::QueryVersion {
+                    major_version: 1,
+                    minor_version: 0,
+                }))
+                .is_ok();
+
+        CaptureConnection {
+            conn,
+            has_shm,
+            has_xfixes: false,
+            has_composite: false,
+            has_present,
+            msc_serial: 0,
+            pixel_formats: HashMap::new(),
+            palettes: HashMap::new(),
+            redirected: HashSet::new(),
+            timed_out: false,
+        }
+    }
+}
+
+impl<C: Borrow<Connection>> CaptureConnection<C> {
+    pub(crate) fn connection(&self) -> &Connection {
+        self.conn.borrow()
+    }
+
+    pub(crate) fn is_broken(&self) -> bool {
+        self.timed_out || self.connection().has_error().is_err()
+    }
+
+    fn check_timeout<T>(&mut self, result: XCapResult<T>) -> XCapResult<T> {
+        if let Err(XCapError::Timeout(_)) = result {
+            self.timed_out = true;
+        }
+
+        result
+    }
+
+    /// Block until the next vblank of the CRTC Present associates with `window`, the one
+    /// showing most of it. Without Present it returns right away, the capture goes ahead
+    /// without the wait.
+    fn wait_for_vblank(&mut self, window: Window) -> XCapResult<()> {
+        if !self.has_present {
+            if !VSYNC_UNSUPPORTED_LOGGED.swap(true, Ordering::Relaxed) {
+                log::warn!("vsync requested but the Present extension is not available");
+            }
+
+            return Ok(());
+        }
+
+        let eid = self.connection().generate_id();
+        self.connection().send_request(&present::SelectInput {
+            eid,
+            window,
+            event_mask: present::EventMask::COMPLETE_NOTIFY,
+        });
+        let special_event = self
+            .connection()
+            .register_for_special_event(Extension::Present, eid);
+
+        self.msc_serial = self.msc_serial.wrapping_add(1);
+        // A target of 0 with a divisor of 1 completes at the very next MSC, i.e. vblank
+        self.connection().send_request(&present::NotifyMsc {
+            window,
+            serial: self.msc_serial,
+            target_msc: 0,
+            divisor: 1,
+            remainder: 0,
+        });
+
+        let result = wait_for_msc(self.connection(), &special_event, self.msc_serial);
+
+        // An empty mask deletes the selection
+        self.connection().send_request(&present::SelectInput {
+            eid,
+            window,
+            event_mask: present::EventMask::empty(),
+        });
+        self.connection()
+            .unregister_for_special_event(special_event);
+
+        self.check_timeout(result)
+    }
+
+    fn get_image(
+        &mut self,
+        window: Window,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        plane_mask: u32,
+    ) -> XCapResult<(ImageData, PixelFormat)> {
+        if XorgCaptureOptions::global().vsync {
+            self.wait_for_vblank(window)?;
+        }
+
+        let result = request_image(
+            self.connection(),
+            self.has_shm,
+            Drawable::Window(window),
+            x,
+            y,
+            width,
+            height,
+            plane_mask,
+        );
+        let (image_data, depth, visual) = self.check_timeout(result)?;
+
+        let pixel_format = self.pixel_format(depth, visual, window)?;
+
+        Ok((image_data, pixel_format))
+    }
+
+    /// Current `(width, height)` of `window`.
+    pub(crate) fn window_size(&mut self, window: Window) -> XCapResult<(u32, u32)> {
+        let cookie = self.connection().send_request(&GetGeometry {
+            drawable: Drawable::Window(window),
+        });
+        let geometry = wait_for_reply(self.connection(), cookie);
+        let geometry = self.check_timeout(geometry)?;
+
+        Ok((geometry.width() as u32, geometry.height() as u32))
+    }
+
+    /// Read the whole of `window`, with a `GetGeometry` sent right in front of the `GetImage`
+    /// so the server answers both for the same window state. An image requested with a size
+    /// the window no longer has is thrown away and read again with the fresh geometry.
+    fn get_window_image(
+        &mut self,
+        window: Window,
+    ) -> XCapResult<(ImageData, PixelFormat, u32, u32)> {
+        let geometry_cookie = self.connection().send_request(&GetGeometry {
+            drawable: Drawable::Window(window),
+        });
+        let geometry = wait_for_reply(self.connection(), geometry_cookie);
+        let geometry = self.check_timeout(geometry)?;
+
+        let size = (geometry.width() as u32, geometry.height() as u32);
+        let ((image_data, pixel_format), (width, height)) =
+            capture_consistent(size, |(width, height)| {
+                let geometry_cookie = self.connection().send_request(&GetGeometry {
+                    drawable: Drawable::Window(window),
+                });
+
+                match self.get_image(window, 0, 0, width, height, ALL_PLANES) {
+                    // The connection is retired, the geometry reply may never come
+                    Err(err @ XCapError::Timeout(_)) => Err(err),
+                    image => {
+                        let geometry = wait_for_reply(self.connection(), geometry_cookie);
+                        let geometry = self.check_timeout(geometry)?;
+
+                        Ok(((geometry.width() as u32, geometry.height() as u32), image))
+                    }
+                }
+            })?;
+
+        Ok((image_data, pixel_format, width, height))
+    }
+
+    /// Read `window` from its Composite backing pixmap, which holds the full content even
+    /// where other windows cover it.
+    ///
+    /// The window stays redirected for as long as this connection lives, so it keeps
+    /// rendering into the pixmap between captures. Without a compositing manager the pixmap
+    /// only has the covered parts once the window repainted after its first redirect, the
+    /// first capture of a window can show stale contents there.
+    fn get_composite_image(
+        &mut self,
+        window: Window,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(ImageData, PixelFormat)> {
+        if !self.has_composite {
+            return Err(XCapError::Unsupported(
+                "Composite extension is not available".to_string(),
+            ));
+        }
+
+        if !self.redirected.contains(&window) {
+            self.connection()
+                .send_and_check_request(&composite::RedirectWindow {
+                    window,
+                    update: composite::Redirect::Automatic,
+                })
+                .map_err(xcb::Error::from)?;
+            self.redirected.insert(window);
+        }
+
+        let pixmap = self.connection().generate_id();
+        let result = self
+            .connection()
+            .send_and_check_request(&composite::NameWindowPixmap { window, pixmap })
+            .map_err(|err| XCapError::from(xcb::Error::from(err)))
+            .and_then(|_| {
+                let result = request_image(
+                    self.connection(),
+                    self.has_shm,
+                    Drawable::Pixmap(pixmap),
+                    0,
+                    0,
+                    width,
+                    height,
+                    ALL_PLANES,
+                );
+                self.connection().send_request(&FreePixmap { pixmap });
+
+                result
+            });
+
+        if result.is_err() {
+            // The window may be gone and its id reused, redirect again next time
+            self.connection()
+                .send_request(&composite::UnredirectWindow {
+                    window,
+                    update: composite::Redirect::Automatic,
+                });
+            self.redirected.remove(&window);
+        }
+
+        let (image_data, depth, visual) = self.check_timeout(result)?;
+        let pixel_format = self.pixel_format(depth, visual, window)?;
+
+        Ok((image_data, pixel_format))
+    }
+
+    /// Capture and decode to RGBA on this connection instead of the shared one.
+    pub(crate) fn capture(
+        &mut self,
+        window: Window,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        let (image_data, pixel_format) = self.get_image(window, x, y, width, height, ALL_PLANES)?;
+
+        let mut rgba = Vec::new();
+        decode(
+            image_data.data(),
+            width,
+            height,
+            &pixel_format,
+            OutputFormat::Rgba,
+            &mut rgba,
+        )?;
+
+        RgbaImage::from_raw(width, height, rgba)
+            .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+    }
+
+    /// Same as [`CaptureConnection::capture`] decoding every `divisor`th pixel, see
+    /// [`xorg_capture_scaled`].
+    pub(crate) fn capture_scaled(
+        &mut self,
+        window: Window,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        divisor: u32,
+    ) -> XCapResult<RgbaImage> {
+        let (image_data, pixel_format) = self.get_image(window, x, y, width, height, ALL_PLANES)?;
+
+        let mut rgba = Vec::new();
+        decode_scaled(
+            image_data.data(),
+            width,
+            height,
+            &pixel_format,
+            OutputFormat::Rgba,
+            divisor,
+            &mut rgba,
+        )?;
+
+        RgbaImage::from_raw(width / divisor, height / divisor, rgba)
+            .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+    }
+
+    /// Capture several `(x, y, width, height)` regions of `window`, sending every core
+    /// `GetImage` before waiting for the first reply, so the round trips overlap.
+    fn capture_regions(
+        &mut self,
+        window: Window,
+        regions: &[(i32, i32, u32, u32)],
+    ) -> XCapResult<Vec<RgbaImage>> {
+        if XorgCaptureOptions::global().vsync {
+            self.wait_for_vblank(window)?;
+        }
+
+        let cookies: Vec<_> = regions
+            .iter()
+            .map(|&(x, y, width, height)| {
+                self.connection().send_request(&GetImage {
+                    format: ImageFormat::ZPixmap,
+                    drawable: Drawable::Window(window),
+                    x: x as i16,
+                    y: y as i16,
+                    width: width as u16,
+                    height: height as u16,
+                    plane_mask: ALL_PLANES,
+                })
+            })
+            .collect();
+
+        // Collect every reply before decoding, a failed request must not leave the others
+        // queued. After a timeout the connection is retired, the rest can be abandoned.
+        let mut replies = Vec::with_capacity(cookies.len());
+        for cookie in cookies {
+            let reply = wait_for_reply(self.connection(), cookie);
+            let timed_out = matches!(reply, Err(XCapError::Timeout(_)));
+            replies.push(reply);
+
+            if timed_out {
+                break;
+            }
+        }
+
+        let mut images = Vec::with_capacity(replies.len());
+        for (reply, &(_, _, width, height)) in replies.into_iter().zip(regions) {
+            let get_image_reply = self.check_timeout(reply)?;
+            let pixel_format =
+                self.pixel_format(get_image_reply.depth(), get_image_reply.visual(), window)?;
+
+            let mut rgba = Vec::new();
+            decode(
+                get_image_reply.data(),
+                width,
+                height,
+                &pixel_format,
+                OutputFormat::Rgba,
+                &mut rgba,
+            )?;
+
+            images.push(RgbaImage::from_raw(width, height, rgba).ok_or_else(|| {
+                XCapError::ImageConversion("RgbaImage::from_raw failed".to_string())
+            })?);
+        }
+
+        Ok(images)
+    }
+
+    /// Pixel format of a reply for `window`, with the palette of its colormap when the visual
+    /// is indexed.
+    fn pixel_format(
+        &mut self,
+        depth: u8,
+        visual: Visualid,
+        window: Window,
+    ) -> XCapResult<PixelFormat> {
+        let pixel_format = match self.pixel_formats.get(&(depth, visual)) {
+            Some(pixel_format) => *pixel_format,
+            None => {
+                let pixel_format = resolve_pixel_format(self.connection(), depth, visual)?;
+                self.pixel_formats.insert((depth, visual), pixel_format);
+
+                pixel_format
+            }
+        };
+
+        let Some(indexed_visual) =
+            find_indexed_visual(self.connection().get_setup(), depth, visual)
+        else {
+            return Ok(pixel_format);
+        };
+
+        let colormap = window_colormap(self.connection(), window, &indexed_visual)?;
+        let palette = match self.palettes.get(&colormap) {
+            Some(&(read_at, palette))
+                if !indexed_visual.writable || read_at.elapsed() < PALETTE_MAX_AGE =>
+            {
+                palette
+            }
+            _ => {
+                let palette = query_palette(self.connection(), colormap, indexed_visual.entries)?;
+                self.palettes.insert(colormap, (Instant::now(), palette));
+
+                palette
+            }
+        };
+
+        Ok(pixel_format.with_palette(palette))
+    }
+}
+
+/// Start over with the geometry `attempt` reports whenever it differs from the `size` the
+/// image was requested with, up to [`RESIZE_ATTEMPTS`] times. A failed image only counts
+/// when the geometry matched, reading a window that just shrank fails by itself.
+fn capture_consistent<T, F>(mut size: (u32, u32), mut attempt: F) -> XCapResult<(T, (u32, u32))>
+where
+    F: FnMut((u32, u32)) -> XCapResult<((u32, u32), XCapResult<T>)>,
+{
+    for _ in 0..RESIZE_ATTEMPTS {
+        let (geometry, image) = attempt(size)?;
+        if geometry == size {
+            return image.map(|image| (image, size));
+        }
+
+        size = geometry;
+    }
+
+    Err(XCapError::new("Window kept resizing during the capture"))
+}
+
+/// Idle connections reused across captures. A capture takes one out for the duration of its
+/// round trips, captures running on several threads at once each get their own.
+static CAPTURE_CONNECTIONS: Mutex<Vec<CaptureConnection>> = Mutex::new(Vec::new());
+
+/// Idle connections kept at most, those handed back beyond that are closed
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// A connection taken out of [`CAPTURE_CONNECTIONS`], handed back when dropped unless it broke.
+struct PooledConnection(Option<CaptureConnection>);
+
+impl Deref for PooledConnection {
+    type Target = CaptureConnection;
+
+    fn deref(&self) -> &CaptureConnection {
+        self.0
+            .as_ref()
+            .expect("connection is only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut CaptureConnection {
+        self.0
+            .as_mut()
+            .expect("connection is only taken when dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(capture_connection) = self.0.take() else {
+            return;
+        };
+        if capture_connection.is_broken() {
+            return;
+        }
+
+        let mut idle = CAPTURE_CONNECTIONS
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(capture_connection);
+        }
+    }
+}
+
+/// Take an idle connection, or open a new one when there is none. The pool is only locked
+/// to take the connection out, never across a request.
+fn get_capture_connection() -> XCapResult<PooledConnection> {
+    let idle = {
+        let mut idle = CAPTURE_CONNECTIONS
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        // Connections that broke while idle are dropped on the way
+        let mut capture_connection = None;
+        while let Some(item) = idle.pop() {
+            if !item.is_broken() {
+                capture_connection = Some(item);
+                break;
+            }
+        }
+
+        capture_connection
+    };
+
+    let capture_connection = match idle {
+        Some(capture_connection) => capture_connection,
+        None => CaptureConnection::new()?,
+    };
+
+    Ok(PooledConnection(Some(capture_connection)))
+}
+
+fn get_image(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(ImageData, PixelFormat)> {
+    get_image_planes(window, x, y, width, height, ALL_PLANES)
+}
+
+/// Same as [`get_image`], also returning how long getting a connection and the transfer
+/// took. The connection is handed back before returning, decoding doesn't hold it.
+fn get_image_timed(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(ImageData, PixelFormat, Duration, Duration)> {
+    let start = Instant::now();
+    let mut capture_connection = get_capture_connection()?;
+    let connected = Instant::now();
+
+    let (image_data, pixel_format) =
+        capture_connection.get_image(window, x, y, width, height, ALL_PLANES)?;
+
+    Ok((
+        image_data,
+        pixel_format,
+        connected - start,
+        connected.elapsed(),
+    ))
+}
+
+fn get_image_planes(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    plane_mask: u32,
+) -> XCapResult<(ImageData, PixelFormat)> {
+    get_capture_connection()?.get_image(window, x, y, width, height, plane_mask)
+}
+
+/// Pixel layout written into the output buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Rgba,
+    /// Same layout as `Rgba` with every color channel already multiplied by `alpha / 255`
+    RgbaPremultiplied,
+    Rgb,
+    /// 4 bytes per pixel in `B, G, R, A` memory order, as most GPU uploads and encoders expect
+    Bgra,
+    /// 3 bytes per pixel in `B, G, R` memory order, OpenCV's default `CV_8UC3` layout
+    Bgr,
+    /// 8 bits luma, `(r * 77 + g * 150 + b * 29) >> 8`
+    Gray,
+}
+
+impl OutputFormat {
+    fn channels(&self) -> usize {
+        match self {
+            OutputFormat::Rgba | OutputFormat::RgbaPremultiplied => 4,
+            OutputFormat::Rgb | OutputFormat::Bgr => 3,
+            OutputFormat::Bgra => 4,
+            OutputFormat::Gray => 1,
+        }
+    }
+
+    fn write(&self, dst: &mut [u8], (r, g, b, a): (u8, u8, u8, u8)) {
+        match self {
+            OutputFormat::Rgba => dst.copy_from_slice(&[r, g, b, a]),
+            OutputFormat::RgbaPremultiplied => {
+                let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                dst.copy_from_slice(&[premultiply(r), premultiply(g), premultiply(b), a])
+            }
+            OutputFormat::Rgb => dst.copy_from_slice(&[r, g, b]),
+            OutputFormat::Bgra => dst.copy_from_slice(&[b, g, r, a]),
+            OutputFormat::Bgr => dst.copy_from_slice(&[b, g, r]),
+            OutputFormat::Gray => {
+                dst[0] = ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8) as u8
+            }
+        }
+    }
+}
+
+/// Convert an already decoded RGBA buffer, e.g. from the Wayland backends, to `output`.
+pub fn convert_rgba(rgba: &[u8], output: OutputFormat, buf: &mut Vec<u8>) {
+    let channels = output.channels();
+
+    buf.resize(rgba.len() / 4 * channels, 0);
+    for (dst, src) in buf.chunks_exact_mut(channels).zip(rgba.chunks_exact(4)) {
+        output.write(dst, (src[0], src[1], src[2], src[3]));
+    }
+}
+
+/// Windows unmapped or resized while capturing can come back short, refuse before indexing.
+fn check_image_len(bytes: &[u8], width: u32, height: u32, format: &PixelFormat) -> XCapResult<()> {
+    let expected = format.image_len(width, height)?;
+    if bytes.len() < expected {
+        return Err(XCapError::IncompleteImageData {
+            expected,
+            got: bytes.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Decode the `GetImage` pixel data into `buf` laid out as `output`. Works on plain data only,
+/// so recorded replies can be decoded without a server.
+///
+/// Channels narrower than 8 bits (depth 8, 15 and 16) are scaled up to the full `0..=255`
+/// range and depth 30 is cut down to 8 bits, so the output layout never depends on the visual.
+fn decode(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    format: &PixelFormat,
+    output: OutputFormat,
+    buf: &mut Vec<u8>,
+) -> XCapResult<()> {
+    decode_scaled(bytes, width, height, format, output, 1, buf)
+}
+
+/// Decode only every `divisor`th pixel of every `divisor`th row (nearest neighbour),
+/// writing a `width / divisor` x `height / divisor` image.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            width,
+            height,
+            divisor,
+            ?output,
+            depth = format.depth,
+            bits_per_pixel = format.bits_per_pixel,
+            bytes = bytes.len(),
+        )
+    )
+)]
+fn decode_scaled(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    format: &PixelFormat,
+    output: OutputFormat,
+    divisor: u32,
+    buf: &mut Vec<u8>,
+) -> XCapResult<()> {
+    if divisor == 0 {
+        return Err(XCapError::new("Scale divisor must be greater than 0"));
+    }
+
+    // Only changes the length when the geometry changes, no reallocation if capacity suffices
+    buf.resize(
+        buffer_len(width / divisor, height / divisor, output.channels())?,
+        0,
+    );
+
+    decode_scaled_into(bytes, width, height, format, output, divisor, buf)
+}
+
+/// [`decode_scaled`] writing into a slice that already has the output's exact length, e.g.
+/// a memory mapping that can't be resized.
+fn decode_scaled_into(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    format: &PixelFormat,
+    output: OutputFormat,
+    divisor: u32,
+    buf: &mut [u8],
+) -> XCapResult<()> {
+    let get_pixel_rgba = get_pixel_reader(format.depth)?;
+    check_image_len(bytes, width, height, format)?;
+
+    // Opaque pixels are unchanged by premultiplying, keep the fast paths for them
+    let output = match (output, format.alpha) {
+        (OutputFormat::RgbaPremultiplied, None) => OutputFormat::Rgba,
+        _ => output,
+    };
+
+    let channels = output.channels();
+    let dst_width = width / divisor;
+
+    let is_packed = divisor == 1 && format.is_packed(width);
+
+    if is_packed {
+        // Swapping the first and third byte works both ways, and BGR from `B, G, R, X` data
+        // is a plain copy dropping the fourth byte
+        match (output, format.byte_offsets()) {
+            (OutputFormat::Rgb, Some([2, 1, 0])) | (OutputFormat::Bgr, Some([0, 1, 2])) => {
+                bgrx_to_rgb(bytes, buf);
+                return Ok(());
+            }
+            (OutputFormat::Rgb, Some([0, 1, 2])) | (OutputFormat::Bgr, Some([2, 1, 0])) => {
+                rgbx_to_rgb(bytes, buf);
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    // The fast paths need the alpha channel, if any, to be the fourth byte
+    let keep_alpha = match format.alpha {
+        Some(alpha) => (alpha.byte_offset(4, format.byte_order) == Some(3)).then_some(true),
+        None => Some(false),
+    };
+
+    if let (true, Some(keep_alpha)) = (is_packed, keep_alpha) {
+        // Swapping the first and third byte works both ways, so the same helpers serve BGRA
+        match (output, format.byte_offsets()) {
+            (OutputFormat::Rgba, Some([2, 1, 0])) | (OutputFormat::Bgra, Some([0, 1, 2])) => {
+                bgrx_to_rgba(bytes, buf, keep_alpha);
+                return Ok(());
+            }
+            (OutputFormat::Rgba, Some([0, 1, 2])) | (OutputFormat::Bgra, Some([2, 1, 0])) => {
+                rgbx_to_rgba(bytes, buf, keep_alpha);
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    let row_len = dst_width as usize * channels;
+    if row_len == 0 {
+        return Ok(());
+    }
+
+    // Every row only reads the shared source bytes, so rows convert independently
+    let convert_row = |(y, row): (usize, &mut [u8])| {
+        let src_y = y as u32 * divisor;
+        for (x, dst) in row.chunks_exact_mut(channels).enumerate() {
+            output.write(
+                dst,
+                get_pixel_rgba(bytes, x as u32 * divisor, src_y, width, format),
+            );
+        }
+    };
+
+    #[cfg(feature = "rayon")]
+    buf.par_chunks_mut(row_len)
+        .enumerate()
+        .for_each(convert_row);
+    #[cfg(not(feature = "rayon"))]
+    buf.chunks_mut(row_len).enumerate().for_each(convert_row);
+
+    Ok(())
+}
+
+/// Undecoded `ZPixmap` data as returned by the X server.
+#[derive(Debug, Clone)]
+pub struct RawImage {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u8,
+    pub bits_per_pixel: u32,
+    /// The server's image byte order, used to assemble multi-byte pixels, `LsbFirst` means
+    /// little endian. Independent of the host's endianness.
+    pub byte_order: ImageOrder,
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    /// Rows of `data` are padded to a multiple of this many bits.
+    pub scanline_pad: u32,
+    pub data: Vec<u8>,
+}
+
+impl RawImage {
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::from_masks(
+            self.depth,
+            self.bits_per_pixel,
+            self.byte_order,
+            (self.red_mask, self.green_mask, self.blue_mask),
+        )
+        .with_scanline_pad(self.scanline_pad)
+    }
+
+    /// Decode the raw data to RGBA.
+    pub fn to_rgba_image(&self) -> XCapResult<RgbaImage> {
+        let mut rgba = Vec::new();
+        decode(
+            &self.data,
+            self.width,
+            self.height,
+            &self.pixel_format(),
+            OutputFormat::Rgba,
+            &mut rgba,
+        )?;
+
+        RgbaImage::from_raw(self.width, self.height, rgba)
+            .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+    }
+}
+
+/// `GetImage` reply kept alive undecoded, so its pixels can be handed out without a copy.
+/// Holds the reply buffer or the MIT-SHM segment until dropped.
+pub struct ImageReply {
+    width: u32,
+    height: u32,
+    image_data: ImageData,
+    pixel_format: PixelFormat,
+}
+
+impl ImageReply {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Tightly packed rows of 4 bytes per pixel in `B, G, R, A` memory order. Borrowed
+    /// straight from the reply when the server already sent exactly that, i.e. depth 32
+    /// little endian ARGB without row padding, so the data lives as long as `self`.
+    /// Any other format is converted into an owned buffer.
+    pub fn bgra(&self) -> XCapResult<Cow<'_, [u8]>> {
+        borrow_bgra(
+            self.image_data.data(),
+            self.width,
+            self.height,
+            &self.pixel_format,
+        )
+    }
+
+    /// Decode the reply lazily, row by row, see [`ImageRows`].
+    pub fn into_rows(self) -> XCapResult<ImageRows> {
+        get_pixel_reader(self.pixel_format.depth)?;
+        check_image_len(
+            self.image_data.data(),
+            self.width,
+            self.height,
+            &self.pixel_format,
+        )?;
+
+        let row = vec![0; buffer_len(self.width, 1, OutputFormat::Rgba.channels())?];
+
+        Ok(ImageRows {
+            reply: self,
+            row,
+            next_y: 0,
+        })
+    }
+}
+
+/// Decode row `y` of a `width` pixels wide image into `row`, sized to one RGBA row.
+fn decode_row(
+    bytes: &[u8],
+    width: u32,
+    y: u32,
+    format: &PixelFormat,
+    row: &mut [u8],
+) -> XCapResult<()> {
+    let stride = format.stride(width);
+    let start = y as usize * stride;
+    let row_bytes = bytes
+        .get(start..start + stride)
+        .ok_or(XCapError::IncompleteImageData {
+            expected: start + stride,
+            got: bytes.len(),
+        })?;
+
+    decode_scaled_into(row_bytes, width, 1, format, OutputFormat::Rgba, 1, row)
+}
+
+/// Rows of an [`ImageReply`] decoded to RGBA one at a time, each into the same row buffer,
+/// so a streaming consumer such as an encoder needs no memory beyond the reply and one row.
+///
+/// It isn't an [`Iterator`] because every row borrows the buffer the next one overwrites.
+pub struct ImageRows {
+    reply: ImageReply,
+    row: Vec<u8>,
+    next_y: u32,
+}
+
+impl ImageRows {
+    pub fn width(&self) -> u32 {
+        self.reply.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.reply.height
+    }
+
+    /// The next row, `None` once all rows were handed out. A row that fails to decode is
+    /// reported as an error rather than ending the rows early, so a truncated image can't
+    /// pass for a complete one.
+    pub fn next_row(&mut self) -> Option<XCapResult<&[u8]>> {
+        if self.next_y >= self.reply.height {
+            return None;
+        }
+
+        let result = decode_row(
+            self.reply.image_data.data(),
+            self.reply.width,
+            self.next_y,
+            &self.reply.pixel_format,
+            &mut self.row,
+        );
+        self.next_y += 1;
+
+        Some(result.map(|_| self.row.as_slice()))
+    }
+}
+
+fn borrow_bgra<'a>(
+    bytes: &'a [u8],
+    width: u32,
+    height: u32,
+    format: &PixelFormat,
+) -> XCapResult<Cow<'a, [u8]>> {
+    check_image_len(bytes, width, height, format)?;
+
+    let has_alpha_byte = format
+        .alpha
+        .is_some_and(|alpha| alpha.byte_offset(4, format.byte_order) == Some(3));
+
+    if has_alpha_byte && format.is_packed(width) && format.byte_offsets() == Some([2, 1, 0]) {
+        return Ok(Cow::Borrowed(&bytes[..format.image_len(width, height)?]));
+    }
+
+    let mut bgra = Vec::new();
+    decode(bytes, width, height, format, OutputFormat::Bgra, &mut bgra)?;
+
+    Ok(Cow::Owned(bgra))
+}
+
+/// Capture without decoding, keeping the reply so [`ImageReply::bgra`] can borrow from it.
+pub fn xorg_capture_reply(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<ImageReply> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+
+    Ok(ImageReply {
+        width,
+        height,
+        image_data,
+        pixel_format,
+    })
+}
+
+pub fn xorg_capture_raw(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RawImage> {
+    xorg_capture_raw_planes(window, x, y, width, height, ALL_PLANES)
+}
+
+/// Same as [`xorg_capture_raw`] reading only the bit planes set in `plane_mask`. The server
+/// still returns every bit of each pixel, bits of planes outside the mask come back as 0.
+pub fn xorg_capture_raw_planes(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    plane_mask: u32,
+) -> XCapResult<RawImage> {
+    let (image_data, pixel_format) = get_image_planes(window, x, y, width, height, plane_mask)?;
+
+    Ok(RawImage {
+        width,
+        height,
+        depth: pixel_format.depth,
+        bits_per_pixel: pixel_format.bits_per_pixel,
+        byte_order: pixel_format.byte_order,
+        red_mask: pixel_format.red.mask(),
+        green_mask: pixel_format.green.mask(),
+        blue_mask: pixel_format.blue.mask(),
+        scanline_pad: pixel_format.scanline_pad,
+        data: image_data.data().to_vec(),
+    })
+}
+
+/// Capture into an existing buffer laid out as `output`, `buf` is only resized
+/// when the geometry changes. Returns the filled `(width, height)`.
+pub fn xorg_capture_with(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    output: OutputFormat,
+    buf: &mut Vec<u8>,
+) -> XCapResult<(u32, u32)> {
+    let (image_data, pixel_format, connect_time, transfer_time) =
+        get_image_timed(window, x, y, width, height)?;
+    let transferred = Instant::now();
+
+    decode(image_data.data(), width, height, &pixel_format, output, buf)?;
+
+    if let Some(callback) = CaptureTimings::callback() {
+        callback(CaptureTimings {
+            connect_time,
+            transfer_time,
+            decode_time: transferred.elapsed(),
+        });
+    }
+
+    Ok((width, height))
+}
+
+/// Capture into an existing RGBA buffer, `buf` is only resized when the
+/// geometry changes. Returns the filled `(width, height)`.
+pub fn xorg_capture_into(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    buf: &mut Vec<u8>,
+) -> XCapResult<(u32, u32)> {
+    xorg_capture_with(window, x, y, width, height, OutputFormat::Rgba, buf)
+}
+
+pub fn xorg_capture(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    let mut rgba = Vec::new();
+    xorg_capture_into(window, x, y, width, height, &mut rgba)?;
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+/// Capture the whole of `window`, sized by a geometry query pipelined with the image request
+/// instead of a size read earlier, which a resize in between would leave stale.
+pub fn xorg_capture_window(window: Window) -> XCapResult<RgbaImage> {
+    let start = Instant::now();
+    let (image_data, pixel_format, width, height, connected) = {
+        let mut capture_connection = get_capture_connection()?;
+        let connected = Instant::now();
+
+        let (image_data, pixel_format, width, height) =
+            capture_connection.get_window_image(window)?;
+
+        (image_data, pixel_format, width, height, connected)
+    };
+    let transferred = Instant::now();
+
+    let mut rgba = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        &mut rgba,
+    )?;
+
+    if let Some(callback) = CaptureTimings::callback() {
+        callback(CaptureTimings {
+            connect_time: connected - start,
+            transfer_time: transferred - connected,
+            decode_time: transferred.elapsed(),
+        });
+    }
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+/// Capture several regions of `window` on one pooled connection with pipelined requests.
+pub fn xorg_capture_regions(
+    window: Window,
+    regions: &[(i32, i32, u32, u32)],
+) -> XCapResult<Vec<RgbaImage>> {
+    get_capture_connection()?.capture_regions(window, regions)
+}
+
+/// Time spent in each stage of a capture made with [`xorg_capture_timed`], or of every
+/// capture once a [`CaptureTimings::set_callback`] callback is registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CaptureTimings {
+    /// Getting a connection from the pool, including connecting when none was idle
+    pub connect_time: Duration,
+    /// `GetImage` round trip, the pixels are in memory afterwards
+    pub transfer_time: Duration,
+    /// Converting the server's pixels to RGBA
+    pub decode_time: Duration,
+}
+
+type TimingsCallback = dyn Fn(CaptureTimings) + Send + Sync;
+
+static CAPTURE_TIMINGS_CALLBACK: RwLock<Option<Arc<TimingsCallback>>> = RwLock::new(None);
+
+impl CaptureTimings {
+    /// Have `callback` called with the timings of every capture decoded from the shared
+    /// connection, e.g. by [`crate::Monitor::capture_image`], [`crate::Monitor::capture_region`]
+    /// and the `_into` variants, `None` removes it. It runs on the capturing thread, so it
+    /// should return quickly. Closures can capture state, e.g. a channel to a metrics thread.
+    pub fn set_callback(callback: Option<Box<dyn Fn(CaptureTimings) + Send + Sync>>) {
+        *CAPTURE_TIMINGS_CALLBACK
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = callback.map(Arc::from);
+    }
+
+    /// The callback is cloned out of the lock, so it can itself call [`Self::set_callback`].
+    fn callback() -> Option<Arc<TimingsCallback>> {
+        CAPTURE_TIMINGS_CALLBACK
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+/// Same as [`xorg_capture`] also measuring how long each stage took.
+pub fn xorg_capture_timed(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(RgbaImage, CaptureTimings)> {
+    let (image_data, pixel_format, connect_time, transfer_time) =
+        get_image_timed(window, x, y, width, height)?;
+    let transferred = Instant::now();
+
+    let mut rgba = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        &mut rgba,
+    )?;
+    let rgba_image = RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))?;
+
+    let timings = CaptureTimings {
+        connect_time,
+        transfer_time,
+        decode_time: transferred.elapsed(),
+    };
+
+    Ok((rgba_image, timings))
+}
+
+pub fn xorg_capture_with_connection(
+    conn: &Connection,
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<RgbaImage> {
+    let (image_data, pixel_format) =
+        CaptureConnection::borrowed(conn).get_image(window, x, y, width, height, ALL_PLANES)?;
+
+    let mut rgba = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        &mut rgba,
+    )?;
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+/// Capture as RGBA when the visual has an alpha channel, as RGB otherwise.
+pub fn xorg_capture_dynamic(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<DynamicImage> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+
+    let output = if pixel_format.alpha.is_some() {
+        OutputFormat::Rgba
+    } else {
+        OutputFormat::Rgb
+    };
+
+    let mut buf = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        output,
+        &mut buf,
+    )?;
+
+    let dynamic_image = if output == OutputFormat::Rgba {
+        RgbaImage::from_raw(width, height, buf).map(DynamicImage::ImageRgba8)
+    } else {
+        RgbImage::from_raw(width, height, buf).map(DynamicImage::ImageRgb8)
+    };
+
+    dynamic_image
+        .ok_or_else(|| XCapError::ImageConversion("DynamicImage from_raw failed".to_string()))
+}
+
+/// Whether decoded `rgba` pixels make use of the alpha channel. Visuals without an alpha
+/// plane are opaque by definition, otherwise the pixels are scanned for any alpha below 255.
+fn alpha_significant(format: &PixelFormat, rgba: &[u8]) -> bool {
+    format.alpha.is_some() && rgba.as_chunks::<4>().0.iter().any(|[.., a]| *a != 255)
+}
+
+/// Capture as RGBA, also reporting whether any pixel is not fully opaque.
+pub fn xorg_capture_alpha(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(RgbaImage, bool)> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+
+    let mut rgba = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        &mut rgba,
+    )?;
+    let alpha_significant = alpha_significant(&pixel_format, &rgba);
+
+    let rgba_image = RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))?;
+
+    Ok((rgba_image, alpha_significant))
+}
+
+pub fn xorg_capture_scaled(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    divisor: u32,
+) -> XCapResult<RgbaImage> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+
+    let mut rgba = Vec::new();
+    decode_scaled(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        divisor,
+        &mut rgba,
+    )?;
+
+    RgbaImage::from_raw(width / divisor, height / divisor, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+pub fn xorg_capture_gray(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<GrayImage> {
+    let mut luma = Vec::new();
+    xorg_capture_with(window, x, y, width, height, OutputFormat::Gray, &mut luma)?;
+
+    GrayImage::from_raw(width, height, luma)
+        .ok_or_else(|| XCapError::ImageConversion("GrayImage::from_raw failed".to_string()))
+}
+
+/// Capture a window through its Composite backing pixmap, see `get_composite_image`.
+pub fn xorg_capture_composite(window: Window, width: u32, height: u32) -> XCapResult<RgbaImage> {
+    let mut capture_connection = get_capture_connection()?;
+    let (image_data, pixel_format) =
+        capture_connection.get_composite_image(window, width, height)?;
+    drop(capture_connection);
+
+    let mut rgba = Vec::new();
+    decode(
+        image_data.data(),
+        width,
+        height,
+        &pixel_format,
+        OutputFormat::Rgba,
+        &mut rgba,
+    )?;
+
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
+}
+
+/// Capture straight to NV12, the planes are computed from the server's pixels while decoding.
+pub fn xorg_capture_nv12(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    matrix: YuvMatrix,
+) -> XCapResult<Nv12Image> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+    let get_pixel_rgba = get_pixel_reader(pixel_format.depth)?;
+
+    let bytes = image_data.data();
+    check_image_len(bytes, width, height, &pixel_format)?;
+
+    Ok(to_nv12(width, height, matrix, |x, y| {
+        get_pixel_rgba(bytes, x, y, width, &pixel_format)
+    }))
+}
+
+/// Capture straight to normalized `f32` pixels, converted from the server's pixels while
+/// decoding.
+pub fn xorg_capture_f32(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    standardization: Option<Standardization>,
+) -> XCapResult<Rgba32FImage> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+    let get_pixel_rgba = get_pixel_reader(pixel_format.depth)?;
+
+    let bytes = image_data.data();
+    check_image_len(bytes, width, height, &pixel_format)?;
+
+    Ok(to_f32(width, height, standardization, |x, y| {
+        get_pixel_rgba(bytes, x, y, width, &pixel_format)
+    }))
+}
+
+/// Capture into separate R, G, B and A planes, filled from the server's pixels while decoding.
+pub fn xorg_capture_planar(
+    window: Window,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<PlanarImage> {
+    let (image_data, pixel_format) = get_image(window, x, y, width, height)?;
+    let get_pixel_rgba = get_pixel_reader(pixel_format.depth)?;
+
+    let bytes = image_data.data();
+    check_image_len(bytes, width, height, &pixel_format)?;
+
+    Ok(to_planar(width, height, |x, y| {
+        get_pixel_rgba(bytes, x, y, width, &pixel_format)
+    }))
+}
+
+/// Capture straight into a memory mapped file sized to the RGBA output. The region is
+/// requested in bands of rows, so neither the reply nor the decoded pixels are ever held
+/// in memory at full size. Returns the filled `(width, height)` and the pixel layout.
+pub fn xorg_capture_to_mmap(
+    window: Window,
+    path: &Path,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> XCapResult<(u32, u32, ColorType)> {
+    let channels = OutputFormat::Rgba.channels();
+    let mut mapped_file = MappedFile::create(path, buffer_len(width, height, channels)?)?;
+
+    let row_len = width as usize * channels;
+    let band_height = (MMAP_BAND_LEN / row_len).clamp(1, height as usize) as u32;
+
+    for (index, band) in mapped_file
+        .data_mut()
+        .chunks_mut(band_height as usize * row_len)
+        .enumerate()
+    {
+        let band_y = index as u32 * band_height;
+        let rows = (band.len() / row_len) as u32;
+        let (image_data, pixel_format) = get_image(window, x, y + band_y as i32, width, rows)?;
+
+        decode_scaled_into(
+            image_data.data(),
+            width,
+            rows,
+            &pixel_format,
+            OutputFormat::Rgba,
+            1,
+            band,
+        )?;
+    }
+
+    mapped_file.flush()?;
+
+    Ok((width, height, ColorType::Rgba8))
+}
+
+/// Blend a premultiplied ARGB cursor sprite onto `rgba`, its top left corner placed at
+/// `(left, top)`, parts outside the image are clipped.
+fn blend_cursor(
+    rgba: &mut RgbaImage,
+    left: i64,
+    top: i64,
+    cursor_width: u32,
+    cursor_image: &[u32],
+) {
+    if cursor_width == 0 {
+        return;
+    }
+
+    let width = rgba.width() as i64;
+    let height = rgba.height() as i64;
+
+    for (index, &argb) in cursor_image.iter().enumerate() {
+        let x = left + (index as u32 % cursor_width) as i64;
+        let y = top + (index as u32 / cursor_width) as i64;
+
+        if x < 0 || y < 0 || x >= width || y >= height {
+            continue;
+        }
+
+        let alpha = argb >> 24;
+        if alpha == 0 {
+            continue;
+        }
+
+        let pixel = rgba.get_pixel_mut(x as u32, y as u32);
+        let src = [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8];
+
+        for (dst, src) in pixel.0.iter_mut().zip(src) {
+            *dst = (src as u32 + *dst as u32 * (255 - alpha) / 255).min(255) as u8;
+        }
+    }
+}
+
+/// Read the RGBA value of a single root window pixel, with a 1x1 core `GetImage` on the
+/// pooled connection since a shared memory segment costs more than it saves here.
+pub fn xorg_pixel_color(window: Window, x: i32, y: i32) -> XCapResult<(u8, u8, u8, u8)> {
+    let mut capture_connection = get_capture_connection()?;
+
+    let result = core_get_image(
+        &capture_connection.conn,
+        Drawable::Window(window),
+        x,
+        y,
+        1,
+        1,
+        ALL_PLANES,
+    );
+    let (image_data, depth, visual) = capture_connection.check_timeout(result)?;
+    let pixel_format = capture_connection.pixel_format(depth, visual, window)?;
+    let get_pixel_rgba = get_pixel_reader(pixel_format.depth)?;
+
+    let bytes = image_data.data();
+    let expected = pixel_format.bytes_per_pixel().max(1) as usize;
+    if bytes.len() < expected {
+        return Err(XCapError::IncompleteImageData {
+            expected,
+            got: bytes.len(),
+        });
+    }
+
+    Ok(get_pixel_rgba(bytes, 0, 0, 1, &pixel_format))
+}
+
+/// Composite the pointer onto `rgba`, which holds the root window region starting at `(x, y)`.
+pub fn xorg_composite_cursor(x: i32, y: i32, rgba: &mut RgbaImage) -> XCapResult<()> {
+    let mut capture_connection = get_capture_connection()?;
+
+    if !capture_connection.has_xfixes {
+        return Err(XCapError::new("XFixes extension is not available"));
+    }
+
+    let cookie = capture_connection
+        .connection()
+        .send_request(&xfixes::GetCursorImage {});
+    let cursor_image_reply = wait_for_reply(capture_connection.connection(), cookie);
+    let cursor_image_reply = capture_connection.check_timeout(cursor_image_reply)?;
+
+    // the reported position is the hotspot, the sprite starts up and left of it
+    let left = cursor_image_reply.x() as i64 - cursor_image_reply.xhot() as i64 - x as i64;
+    let top = cursor_image_reply.y() as i64 - cursor_image_reply.yhot() as i64 - y as i64;
+
+    blend_cursor(
+        rgba,
+        left,
+        top,
+        cursor_image_reply.width() as u32,
+        cursor_image_reply.cursor_image(),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, mem};
+
+    use xcb::x::VisualClass;
+
+    use super::{super::utils::get_current_screen_buf, *};
+
+    #[test]
+    fn test_get_pixel8_rgba() {
+        // RRRGGGBB: 111_000_11, 010_101_01
+        let bytes = [0b1110_0011, 0b0101_0101];
+
+        for byte_order in [ImageOrder::LsbFirst, ImageOrder::MsbFirst] {
+            let format = PixelFormat::new(8, 8, byte_order, None);
+
+            assert_eq!(
+                get_pixel8_rgba(&bytes, 0, 0, 2, &format),
+                (255, 0, 255, 255)
+            );
+            assert_eq!(
+                get_pixel8_rgba(&bytes, 1, 0, 2, &format),
+                (72, 182, 85, 255)
+            );
+        }
+
+        // PseudoColor pixels are palette indices
+        let mut palette = [(0, 0, 0); 256];
+        palette[0b1110_0011] = (10, 20, 30);
+        let format = PixelFormat::new(8, 8, ImageOrder::LsbFirst, None).with_palette(palette);
+        assert_eq!(get_pixel8_rgba(&bytes, 0, 0, 2, &format), (10, 20, 30, 255));
+        assert_eq!(get_pixel8_rgba(&bytes, 1, 0, 2, &format), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn test_get_pixel16_rgba_565() {
+        let format = PixelFormat::new(16, 16, ImageOrder::LsbFirst, None);
+        // 11111_000000_00000, 00000_111111_00000, 10000_100000_10000
+        let words: [u16; 3] = [0xf800, 0x07e0, 0x8410];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+        assert_eq!(get_pixel16_rgba(&bytes, 0, 0, 3, &format), (255, 0, 0, 255));
+        assert_eq!(get_pixel16_rgba(&bytes, 1, 0, 3, &format), (0, 255, 0, 255));
+        assert_eq!(
+            get_pixel16_rgba(&bytes, 2, 0, 3, &format),
+            (131, 129, 131, 255)
+        );
+    }
+
+    #[test]
+    fn test_get_pixel16_rgba_555() {
+        let rgb555 = visual(0x7c00, 0x3e0, 0x1f);
+        let format = PixelFormat::new(15, 16, ImageOrder::MsbFirst, Some(&rgb555));
+        // 0_11111_00000_00000, 0_00000_11111_00000, 0_10000_10000_10000
+        let words: [u16; 3] = [0x7c00, 0x03e0, 0x4210];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+        assert_eq!(get_pixel16_rgba(&bytes, 0, 0, 3, &format), (255, 0, 0, 255));
+        assert_eq!(get_pixel16_rgba(&bytes, 1, 0, 3, &format), (0, 255, 0, 255));
+        assert_eq!(
+            get_pixel16_rgba(&bytes, 2, 0, 3, &format),
+            (131, 131, 131, 255)
+        );
+    }
+
+    #[test]
+    fn test_capture_consistent() {
+        // Resized to 30x20 between the first size query and the image request
+        let mut requested = Vec::new();
+        let (image, size) = capture_consistent((40, 20), |size| {
+            requested.push(size);
+            let image = if size == (40, 20) {
+                Err(XCapError::new("BadMatch"))
+            } else {
+                Ok(size)
+            };
+
+            Ok(((30, 20), image))
+        })
+        .unwrap();
+        assert_eq!((image, size), ((30, 20), (30, 20)));
+        assert_eq!(requested, [(40, 20), (30, 20)]);
+
+        // A window resized on every attempt gives up instead of looping forever
+        let mut width = 10;
+        let resizing = capture_consistent((width, 10), |_| {
+            width += 1;
+            Ok(((width, 10), Ok(())))
+        });
+        assert!(resizing.is_err());
+    }
+
+    #[test]
+    fn test_bgrx_to_rgba() {
+        let src = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut rgba = [0u8; 8];
+        bgrx_to_rgba(&src, &mut rgba, false);
+
+        assert_eq!(rgba, [3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn test_rgbx_to_rgba() {
+        let src = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut rgba = [0u8; 8];
+        rgbx_to_rgba(&src, &mut rgba, false);
+
+        assert_eq!(rgba, [1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn test_bgrx_to_rgb() {
+        let src = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut rgb = [0u8; 6];
+        bgrx_to_rgb(&src, &mut rgb);
+
+        assert_eq!(rgb, [3, 2, 1, 6, 5, 4]);
+    }
+
+    #[test]
+    fn test_rgbx_to_rgb() {
+        let src = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut rgb = [0u8; 6];
+        rgbx_to_rgb(&src, &mut rgb);
+
+        assert_eq!(rgb, [1, 2, 3, 4, 5, 6]);
+    }
+
+    fn visual(red_mask: u32, green_mask: u32, blue_mask: u32) -> Visualtype {
+        Visualtype::new(
+            0x21,
+            VisualClass::TrueColor,
+            8,
+            256,
+            red_mask,
+            green_mask,
+            blue_mask,
+        )
+    }
+
+    #[test]
+    fn test_pixel_format_byte_offsets() {
+        let bgrx = visual(0xff0000, 0xff00, 0xff);
+        let rgbx = visual(0xff, 0xff00, 0xff0000);
+
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&bgrx));
+        assert_eq!(format.byte_offsets(), Some([2, 1, 0]));
+
+        let format = PixelFormat::new(24, 32, ImageOrder::MsbFirst, Some(&bgrx));
+        assert_eq!(format.byte_offsets(), Some([1, 2, 3]));
+
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&rgbx));
+        assert_eq!(format.byte_offsets(), Some([0, 1, 2]));
+    }
+
+    #[test]
+    fn test_get_pixel24_32_rgba_masks() {
+        // Red in the low byte, blue in the high byte
+        let rgbx = visual(0xff, 0xff00, 0xff0000);
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&rgbx));
+        let bytes = [10, 20, 30, 0];
+
+        assert_eq!(
+            get_pixel24_32_rgba(&bytes, 0, 0, 1, &format),
+            (10, 20, 30, 255)
+        );
+
+        // Unaligned masks can't take the byte offsets fast path
+        let unaligned = visual(0xff << 20, 0xff << 12, 0xff << 4);
+        let format = PixelFormat::new(24, 32, ImageOrder::MsbFirst, Some(&unaligned));
+        let pixel: u32 = (10 << 20) | (20 << 12) | (30 << 4);
+
+        assert_eq!(format.byte_offsets(), None);
+        assert_eq!(
+            get_pixel24_32_rgba(&pixel.to_be_bytes(), 0, 0, 1, &format),
+            (10, 20, 30, 255)
+        );
+    }
+
+    #[test]
+    fn test_get_pixel30_rgba() {
+        let format = PixelFormat::new(30, 32, ImageOrder::LsbFirst, None);
+        // r = 0x3ff, g = 0x200, b = 0x004
+        let pixel: u32 = (0x3ff << 20) | (0x200 << 10) | 0x004;
+
+        assert_eq!(format.byte_offsets(), None);
+        assert_eq!(
+            get_pixel24_32_rgba(&pixel.to_le_bytes(), 0, 0, 1, &format),
+            (255, 128, 1, 255)
+        );
+
+        let x2b10g10r10 = visual(0x3ff, 0xffc00, 0x3ff00000);
+        let format = PixelFormat::new(30, 32, ImageOrder::MsbFirst, Some(&x2b10g10r10));
+        let pixel: u32 = (0x004 << 20) | (0x200 << 10) | 0x3ff;
+
+        assert_eq!(
+            get_pixel24_32_rgba(&pixel.to_be_bytes(), 0, 0, 1, &format),
+            (255, 128, 1, 255)
+        );
+    }
+
+    #[test]
+    fn test_decode_rgbx_visual() {
+        let rgbx = visual(0xff, 0xff00, 0xff0000);
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&rgbx));
+        let bytes = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut buf = Vec::new();
+
+        decode(&bytes, 2, 1, &format, OutputFormat::Rgb, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+
+        decode(&bytes, 2, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn test_raw_image_to_rgba_image() {
+        let raw_image = RawImage {
+            width: 2,
+            height: 1,
+            depth: 24,
+            bits_per_pixel: 32,
+            byte_order: ImageOrder::LsbFirst,
+            red_mask: 0xff0000,
+            green_mask: 0xff00,
+            blue_mask: 0xff,
+            scanline_pad: 32,
+            data: vec![1, 2, 3, 0, 4, 5, 6, 0],
+        };
+        let format = raw_image.pixel_format();
+
+        assert_eq!(format.red.mask(), raw_image.red_mask);
+        assert_eq!(format.blue.mask(), raw_image.blue_mask);
+        assert_eq!(
+            raw_image.to_rgba_image().unwrap().into_raw(),
+            [3, 2, 1, 255, 6, 5, 4, 255]
+        );
+    }
+
+    /// 2x2 `ZPixmap` reply as sent by the server, rows padded to 32 bits
+    fn reply(
+        depth: u8,
+        bits_per_pixel: u32,
+        byte_order: ImageOrder,
+        (red_mask, green_mask, blue_mask): (u32, u32, u32),
+        data: &[u8],
+    ) -> RawImage {
+        RawImage {
+            width: 2,
+            height: 2,
+            depth,
+            bits_per_pixel,
+            byte_order,
+            red_mask,
+            green_mask,
+            blue_mask,
+            scanline_pad: 32,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_decode_recorded_replies() {
+        use ImageOrder::{LsbFirst, MsbFirst};
+
+        const RGB: (u32, u32, u32) = (0xff0000, 0xff00, 0xff);
+        // Every reply holds red, green on the first row and blue, white on the second
+        #[rustfmt::skip]
+        let replies = [
+            reply(8, 8, LsbFirst, (0xe0, 0x1c, 0x03), &[0xe0, 0x1c, 0, 0, 0x03, 0xff, 0, 0]),
+            reply(15, 16, LsbFirst, (0x7c00, 0x3e0, 0x1f), &[0, 0x7c, 0xe0, 0x03, 0x1f, 0, 0xff, 0x7f]),
+            reply(16, 16, LsbFirst, (0xf800, 0x7e0, 0x1f), &[0, 0xf8, 0xe0, 0x07, 0x1f, 0, 0xff, 0xff]),
+            reply(16, 16, MsbFirst, (0xf800, 0x7e0, 0x1f), &[0xf8, 0, 0x07, 0xe0, 0, 0x1f, 0xff, 0xff]),
+            reply(24, 24, LsbFirst, RGB, &[0, 0, 255, 0, 255, 0, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0]),
+            reply(24, 32, LsbFirst, RGB, &[0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0, 0, 255, 255, 255, 0]),
+            reply(24, 32, MsbFirst, RGB, &[0, 255, 0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0, 255, 255, 255]),
+            reply(30, 32, LsbFirst, (0x3ff00000, 0xffc00, 0x3ff), &[0, 0, 0xf0, 0x3f, 0, 0xfc, 0x0f, 0, 0xff, 0x03, 0, 0, 0xff, 0xff, 0xff, 0x3f]),
+            reply(32, 32, LsbFirst, RGB, &[0, 0, 255, 255, 0, 255, 0, 255, 255, 0, 0, 255, 255, 255, 255, 255]),
+        ];
+
+        for reply in replies {
+            assert_eq!(
+                reply.to_rgba_image().unwrap().into_raw(),
+                [
+                    255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255
+                ],
+                "depth {} bpp {} {:?}",
+                reply.depth,
+                reply.bits_per_pixel,
+                reply.byte_order
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_padded_rows() {
+        // 3 pixels of 16 bits take 6 bytes, padded to 8 with a 32 bits scanline pad
+        let format = PixelFormat::new(16, 16, ImageOrder::LsbFirst, None).with_scanline_pad(32);
+        assert_eq!(format.stride(3), 8);
+
+        let bytes = [
+            0x00, 0xf8, 0xe0, 0x07, 0x1f, 0x00, 0xaa, 0xaa, //
+            0x1f, 0x00, 0xe0, 0x07, 0x00, 0xf8, 0xaa, 0xaa, //
+        ];
+        let mut buf = Vec::new();
+
+        decode(&bytes, 3, 2, &format, OutputFormat::Rgb, &mut buf).unwrap();
+        assert_eq!(
+            buf,
+            [
+                255, 0, 0, 0, 255, 0, 0, 0, 255, //
+                0, 0, 255, 0, 255, 0, 255, 0, 0,
+            ]
+        );
+
+        let result = decode(&bytes[..12], 3, 2, &format, OutputFormat::Rgb, &mut buf);
+        assert!(matches!(
+            result,
+            Err(XCapError::IncompleteImageData {
+                expected: 16,
+                got: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn test_shm_image_drop() {
+        let shmids: Vec<_> = (0..64)
+            .map(|_| ShmImage::new(4096).unwrap().shmid)
+            .collect();
+
+        // Every segment is marked for removal and gone once detached
+        for shmid in shmids {
+            let mut stat = unsafe { mem::zeroed::<libc::shmid_ds>() };
+            assert_eq!(
+                unsafe { libc::shmctl(shmid, libc::IPC_STAT, &mut stat) },
+                -1
+            );
+        }
+    }
+
+    #[test]
+    fn test_capture_connection_drop() {
+        let open_fds = || fs::read_dir("/proc/self/fd").unwrap().count();
+        let root = get_current_screen_buf().unwrap().root();
+
+        let before = open_fds();
+        for _ in 0..64 {
+            let mut connection = CaptureConnection::new().unwrap();
+            connection.capture(root, 0, 0, 1, 1).unwrap();
+        }
+
+        // Other tests open files concurrently, a leak would show up as one fd per connection
+        assert!(open_fds() < before + 8);
+    }
+
+    #[test]
+    fn test_decode_low_depth_to_rgba() {
+        let mut buf = Vec::new();
+
+        // RRRGGGBB: white, black, pure blue
+        let format = PixelFormat::new(8, 8, ImageOrder::LsbFirst, None);
+        decode(
+            &[0xff, 0x00, 0x03],
+            3,
+            1,
+            &format,
+            OutputFormat::Rgba,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf, [255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 255, 255]);
+
+        // RGB565: white, black, pure green
+        let format = PixelFormat::new(16, 16, ImageOrder::LsbFirst, None);
+        let bytes = [0xff, 0xff, 0x00, 0x00, 0xe0, 0x07];
+        decode(&bytes, 3, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [255, 255, 255, 255, 0, 0, 0, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_gray() {
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        // white, pure red, pure green, pure blue in B, G, R, X order
+        let bytes = [255, 255, 255, 0, 0, 0, 255, 0, 0, 255, 0, 0, 255, 0, 0, 0];
+        let mut buf = Vec::new();
+
+        decode(&bytes, 4, 1, &format, OutputFormat::Gray, &mut buf).unwrap();
+        assert_eq!(buf, [255, 76, 149, 28]);
+
+        convert_rgba(
+            &[255, 0, 0, 255, 0, 0, 255, 255],
+            OutputFormat::Gray,
+            &mut buf,
+        );
+        assert_eq!(buf, [76, 28]);
+    }
+
+    #[test]
+    fn test_decode_unsupported_depth() {
+        let format = PixelFormat::new(12, 16, ImageOrder::LsbFirst, None);
+        let mut buf = Vec::new();
+
+        let result = decode(&[0; 4], 2, 1, &format, OutputFormat::Rgba, &mut buf);
+        assert!(matches!(result, Err(XCapError::UnsupportedDepth(12))));
+
+        let format = PixelFormat::new(4, 8, ImageOrder::LsbFirst, None);
+        let result = decode(&[0; 4], 2, 1, &format, OutputFormat::Rgba, &mut buf);
+        assert!(matches!(
+            result,
+            Err(XCapError::UnsupportedMonochromeDepth(4))
+        ));
+    }
+
+    #[test]
+    fn test_blend_cursor() {
+        let mut rgba = RgbaImage::from_pixel(2, 2, image::Rgba([100, 100, 100, 255]));
+        // opaque red, half transparent premultiplied white, transparent
+        let cursor_image = [0xffff0000, 0x80808080, 0x00000000, 0xff00ff00];
+
+        // shifted up and left by one, only the last sprite pixel lands inside the image
+        blend_cursor(&mut rgba, -1, -1, 2, &cursor_image);
+        assert_eq!(rgba.get_pixel(0, 0).0, [0, 255, 0, 255]);
+        assert_eq!(rgba.get_pixel(1, 1).0, [100, 100, 100, 255]);
+
+        blend_cursor(&mut rgba, 0, 0, 2, &cursor_image);
+        assert_eq!(rgba.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(rgba.get_pixel(1, 0).0, [177, 177, 177, 255]);
+        assert_eq!(rgba.get_pixel(0, 1).0, [100, 100, 100, 255]);
+        assert_eq!(rgba.get_pixel(1, 1).0, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_scaled() {
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        // 4x2 BGRX, keeps the pixels at (0, 0) and (2, 0)
+        let bytes: Vec<u8> = (0..8).flat_map(|i| [i, i, i, 0]).collect();
+        let mut buf = Vec::new();
+
+        decode_scaled(&bytes, 4, 2, &format, OutputFormat::Rgba, 2, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 0, 255, 2, 2, 2, 255]);
+
+        decode_scaled(&bytes, 4, 2, &format, OutputFormat::Rgb, 3, &mut buf).unwrap();
+        assert!(buf.is_empty());
+
+        assert!(decode_scaled(&bytes, 4, 2, &format, OutputFormat::Rgba, 0, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_buffer_len() {
+        assert_eq!(buffer_len(3840, 2160, 4).unwrap(), 3840 * 2160 * 4);
+        assert_eq!(buffer_len(0, 2160, 4).unwrap(), 0);
+
+        assert!(matches!(
+            buffer_len(u32::MAX, u32::MAX, 4),
+            Err(XCapError::RegionTooLarge {
+                width: u32::MAX,
+                height: u32::MAX
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_bgra() {
+        let bytes = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut buf = Vec::new();
+
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        decode(&bytes, 2, 1, &format, OutputFormat::Bgra, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 255, 4, 5, 6, 255]);
+
+        let format = PixelFormat::new(24, 32, ImageOrder::MsbFirst, None);
+        decode(&bytes, 2, 1, &format, OutputFormat::Bgra, &mut buf).unwrap();
+        assert_eq!(buf, [0, 3, 2, 255, 0, 6, 5, 255]);
+    }
+
+    #[test]
+    fn test_decode_bgr() {
+        let bytes = [1, 2, 3, 0, 4, 5, 6, 0];
+        let mut buf = Vec::new();
+
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        decode(&bytes, 2, 1, &format, OutputFormat::Bgr, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+
+        let format = PixelFormat::new(24, 32, ImageOrder::MsbFirst, None);
+        decode(&bytes, 2, 1, &format, OutputFormat::Bgr, &mut buf).unwrap();
+        assert_eq!(buf, [0, 3, 2, 0, 6, 5]);
+
+        // Depth 16 goes through the generic path
+        let format = PixelFormat::new(16, 16, ImageOrder::LsbFirst, None);
+        decode(&[0x00, 0xf8], 1, 1, &format, OutputFormat::Bgr, &mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_argb_visual() {
+        let argb = visual(0xff0000, 0xff00, 0xff);
+        // B, G, R, A with opaque, half transparent and fully transparent pixels
+        let bytes = [1, 2, 3, 255, 4, 5, 6, 128, 7, 8, 9, 0];
+        let mut buf = Vec::new();
+
+        let format = PixelFormat::new(32, 32, ImageOrder::LsbFirst, Some(&argb));
+        assert_eq!(format.alpha, Some(Channel::from_mask(0xff000000)));
+
+        decode(&bytes, 3, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [3, 2, 1, 255, 6, 5, 4, 128, 9, 8, 7, 0]);
+
+        // Big endian pixels miss the fast path and go through get_pixel24_32_rgba
+        let format = PixelFormat::new(32, 32, ImageOrder::MsbFirst, Some(&argb));
+        let bytes = [128, 3, 2, 1];
+        assert_eq!(
+            get_pixel24_32_rgba(&bytes, 0, 0, 1, &format),
+            (3, 2, 1, 128)
+        );
+
+        // Depth 24 has no alpha plane even though the padding byte is set
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&argb));
+        assert_eq!(format.alpha, None);
+        assert_eq!(
+            get_pixel24_32_rgba(&[1, 2, 3, 128], 0, 0, 1, &format),
+            (3, 2, 1, 255)
+        );
+    }
+
+    #[test]
+    fn test_decode_depth_mismatch() {
+        let argb = visual(0xff0000, 0xff00, 0xff);
+        let bytes = [10, 20, 30, 128];
+        let mut buf = Vec::new();
+
+        // Depth 24 data from an ARGB window still carries its alpha in the fourth byte
+        let format =
+            PixelFormat::new(24, 32, ImageOrder::LsbFirst, Some(&argb)).with_visual_depth(32);
+        decode(&bytes, 1, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [30, 20, 10, 128]);
+
+        // Depth 32 data from a depth 24 window only has padding there
+        let format =
+            PixelFormat::new(32, 32, ImageOrder::LsbFirst, Some(&argb)).with_visual_depth(24);
+        assert_eq!(format.alpha, None);
+        decode(&bytes, 1, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [30, 20, 10, 255]);
+
+        // A 16 bits layout has no room for alpha whatever the visual claims
+        let format = PixelFormat::new(16, 16, ImageOrder::LsbFirst, None).with_visual_depth(32);
+        assert_eq!(format.alpha, None);
+    }
+
+    #[test]
+    fn test_read_pixel_byte_order() {
+        // Assembled from the server's byte order alone, a big endian host reads the same
+        let bytes = [0x12, 0x34, 0x56, 0x78];
+
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        assert_eq!(format.read_pixel(&bytes), 0x78563412);
+        let format = PixelFormat::new(24, 32, ImageOrder::MsbFirst, None);
+        assert_eq!(format.read_pixel(&bytes), 0x12345678);
+    }
+
+    #[test]
+    fn test_decode_msb_first_argb() {
+        let argb = visual(0xff0000, 0xff00, 0xff);
+        // A, R, G, B as sent by a big endian server
+        let bytes = [255, 1, 2, 3, 128, 4, 5, 6];
+        let mut buf = Vec::new();
+
+        let format = PixelFormat::new(32, 32, ImageOrder::MsbFirst, Some(&argb));
+        assert_eq!(format.byte_offsets(), Some([1, 2, 3]));
+
+        decode(&bytes, 2, 1, &format, OutputFormat::Rgba, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3, 255, 4, 5, 6, 128]);
+
+        decode(&bytes, 2, 1, &format, OutputFormat::Bgra, &mut buf).unwrap();
+        assert_eq!(buf, [3, 2, 1, 255, 6, 5, 4, 128]);
+    }
+
+    #[test]
+    fn test_borrow_bgra() {
+        let argb = visual(0xff0000, 0xff00, 0xff);
+        let bytes = [1, 2, 3, 255, 4, 5, 6, 128];
+
+        let format = PixelFormat::new(32, 32, ImageOrder::LsbFirst, Some(&argb));
+        let bgra = borrow_bgra(&bytes, 2, 1, &format).unwrap();
+        assert!(matches!(bgra, Cow::Borrowed(_)));
+        assert_eq!(*bgra, bytes);
+
+        // Depth 24 pads with an undefined byte, alpha has to be filled in
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        let bgra = borrow_bgra(&bytes, 2, 1, &format).unwrap();
+        assert!(matches!(bgra, Cow::Owned(_)));
+        assert_eq!(*bgra, [1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn test_decode_row() {
+        // Rows of 2 pixels of 3 bytes, padded to 8 bytes
+        let rgb = visual(0xff0000, 0xff00, 0xff);
+        let bytes = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0];
+        let format =
+            PixelFormat::new(24, 24, ImageOrder::LsbFirst, Some(&rgb)).with_scanline_pad(32);
+        let mut row = [0; 8];
+
+        decode_row(&bytes, 2, 1, &format, &mut row).unwrap();
+        assert_eq!(row, [9, 8, 7, 255, 12, 11, 10, 255]);
+
+        let result = decode_row(&bytes, 2, 2, &format, &mut row);
+        assert!(matches!(
+            result,
+            Err(XCapError::IncompleteImageData { expected: 24, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_premultiplied() {
+        let argb = visual(0xff0000, 0xff00, 0xff);
+        let bytes = [10, 20, 200, 255, 10, 20, 200, 128, 10, 20, 200, 0];
+        let mut buf = Vec::new();
+
+        let format = PixelFormat::new(32, 32, ImageOrder::LsbFirst, Some(&argb));
+        decode(
+            &bytes,
+            3,
+            1,
+            &format,
+            OutputFormat::RgbaPremultiplied,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf, [200, 20, 10, 255, 100, 10, 5, 128, 0, 0, 0, 0]);
+
+        // Without an alpha plane every pixel is opaque and stays as is
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        decode(
+            &bytes,
+            3,
+            1,
+            &format,
+            OutputFormat::RgbaPremultiplied,
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf, [200, 20, 10, 255, 200, 20, 10, 255, 200, 20, 10, 255]);
+    }
+
+    #[test]
+    fn test_alpha_significant() {
+        let argb = PixelFormat::new(
+            32,
+            32,
+            ImageOrder::LsbFirst,
+            Some(&visual(0xff0000, 0xff00, 0xff)),
+        );
+        assert!(!alpha_significant(&argb, &[1, 2, 3, 255, 4, 5, 6, 255]));
+        assert!(alpha_significant(&argb, &[1, 2, 3, 255, 4, 5, 6, 128]));
+
+        // Depth 24 decodes as opaque whatever the padding byte held
+        let rgb = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        assert!(!alpha_significant(&rgb, &[1, 2, 3, 0]));
+    }
+
+    #[test]
+    fn test_decode_incomplete_image_data() {
+        let format = PixelFormat::new(24, 32, ImageOrder::LsbFirst, None);
+        let mut buf = Vec::new();
+
+        let result = decode(&[0; 12], 2, 2, &format, OutputFormat::Rgba, &mut buf);
+        assert!(matches!(
+            result,
+            Err(XCapError::IncompleteImageData {
+                expected: 16,
+                got: 12
+            })
+        ));
+    }
 }
@@ -44,6 +44,6 @@ pub fn capture(
         }
 
         RgbaImage::from_raw(width as u32, height as u32, buffer)
-            .ok_or_else(|| XCapError::new("RgbaImage::from_raw failed"))
+            .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
     }
 }
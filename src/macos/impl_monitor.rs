@@ -13,6 +13,7 @@ use objc2_foundation::{NSNumber, NSString};
 
 use crate::{
     error::{XCapError, XCapResult},
+    monitor::validate_region,
     video_recorder::Frame,
 };
 
@@ -209,16 +210,16 @@ impl ImplMonitor {
         let monitor_width = self.width()?;
         let monitor_height = self.height()?;
 
-        if width > monitor_width
-            || height > monitor_height
-            || x as u32 + width > monitor_width
-            || y as u32 + height > monitor_height
-        {
-            return Err(XCapError::InvalidCaptureRegion(format!(
-                "Region ({}, {}, {}, {}) is outside monitor bounds ({}, {}, {}, {})",
-                x, y, width, height, monitor_x, monitor_y, monitor_width, monitor_height
-            )));
-        }
+        validate_region(
+            x,
+            y,
+            width,
+            height,
+            monitor_x,
+            monitor_y,
+            monitor_width,
+            monitor_height,
+        )?;
 
         // Create a CGRect for the region to capture
         unsafe {
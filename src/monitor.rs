@@ -1,11 +1,57 @@
-use std::sync::mpsc::Receiver;
+use std::{io::Write, sync::mpsc::Receiver, time::Instant};
+#[cfg(target_os = "linux")]
+use std::{mem, path::Path};
 
-use image::RgbaImage;
+#[cfg(feature = "image")]
+use image::codecs::jpeg::JpegEncoder;
+#[cfg(target_os = "linux")]
+use image::{ColorType, DynamicImage, GrayImage, Rgba32FImage};
+use image::{ImageFormat, RgbaImage, codecs::png::PngEncoder, imageops};
 
+#[cfg(target_os = "linux")]
 use crate::{
-    VideoRecorder, error::XCapResult, platform::impl_monitor::ImplMonitor, video_recorder::Frame,
+    CaptureTimings, FrameStream, ImageReply, ImageRows, Nv12Image, PlanarImage, RawImage,
+    Standardization, YuvMatrix, platform::xorg_capture::buffer_len,
+};
+use crate::{
+    ToneCurve, VideoRecorder, XCapError, error::XCapResult, platform::impl_monitor::ImplMonitor,
+    video_recorder::Frame,
 };
 
+/// A captured region together with when and where it was grabbed.
+#[derive(Debug, Clone)]
+pub struct CaptureFrame {
+    pub image: RgbaImage,
+    /// Taken right before the capture request is issued.
+    pub captured_at: Instant,
+    /// Top left corner of the region in virtual desktop coordinates.
+    pub x: i32,
+    pub y: i32,
+    pub monitor_name: String,
+}
+
+/// How [`Monitor::capture_region_f`] snaps fractional coordinates to pixel edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Both edges move to the pixel boundary left of or above them
+    Floor,
+    /// Both edges move to the nearest pixel boundary
+    #[default]
+    Round,
+    /// Both edges move to the pixel boundary right of or below them
+    Ceil,
+}
+
+impl Rounding {
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Rounding::Floor => value.floor(),
+            Rounding::Round => value.round(),
+            Rounding::Ceil => value.ceil(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Monitor {
     pub(crate) impl_monitor: ImplMonitor,
@@ -18,6 +64,7 @@ impl Monitor {
 }
 
 impl Monitor {
+    /// List all monitors. On X11 servers running several screens, those of every screen.
     pub fn all() -> XCapResult<Vec<Monitor>> {
         let monitors = ImplMonitor::all()?
             .iter()
@@ -32,6 +79,24 @@ impl Monitor {
 
         Ok(Monitor::new(impl_monitor))
     }
+
+    /// The primary monitor, or the first one when the system flags none as primary, which
+    /// happens on some multi-GPU setups. Fails with [`XCapError::NoMonitor`] when there are
+    /// no monitors at all.
+    pub fn primary() -> XCapResult<Monitor> {
+        let monitors = Monitor::all()?;
+
+        for monitor in &monitors {
+            if monitor.is_primary()? {
+                return Ok(monitor.clone());
+            }
+        }
+
+        let monitor = monitors.into_iter().next().ok_or(XCapError::NoMonitor)?;
+        log::trace!("no monitor is marked primary, using the first one");
+
+        Ok(monitor)
+    }
 }
 
 impl Monitor {
@@ -80,18 +145,275 @@ impl Monitor {
     pub fn is_builtin(&self) -> XCapResult<bool> {
         self.impl_monitor.is_builtin()
     }
+
+    /// The screen pixel width and height, i.e. the size of [`Monitor::capture_image`].
+    pub fn size(&self) -> XCapResult<(u32, u32)> {
+        Ok((self.width()?, self.height()?))
+    }
+
+    /// The `(x, y, width, height)` rectangle [`Monitor::capture_region_clamped`] would capture
+    /// for these arguments, without transferring any pixels: the region itself when it is
+    /// valid, otherwise the part of it inside the monitor. A region with nothing inside the
+    /// monitor is an error.
+    pub fn region_geometry(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(u32, u32, u32, u32)> {
+        let (monitor_x, monitor_y) = (self.x()?, self.y()?);
+        let (monitor_width, monitor_height) = self.size()?;
+        let validate = |(x, y, width, height)| {
+            validate_region(
+                x,
+                y,
+                width,
+                height,
+                monitor_x,
+                monitor_y,
+                monitor_width,
+                monitor_height,
+            )
+        };
+
+        let region = (x, y, width, height);
+        let err = match validate(region) {
+            Ok(()) => return Ok(region),
+            Err(err) => err,
+        };
+
+        match clamp_region(region, monitor_width, monitor_height) {
+            Some(clamped) if clamped != region => validate(clamped).map(|_| clamped),
+            _ => Err(err),
+        }
+    }
 }
 
 impl Monitor {
-    /// Capture image of the monitor
+    /// Capture image of the monitor, as 8 bits per channel RGBA whatever the framebuffer depth.
     pub fn capture_image(&self) -> XCapResult<RgbaImage> {
         self.impl_monitor.capture_image()
     }
 
+    /// Capture a monitor relative region, see [`Monitor::try_capture_region`].
     pub fn capture_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<RgbaImage> {
+        self.try_capture_region(x, y, width, height)
+    }
+
+    /// Capture a monitor relative region. Empty, out of bounds, oversized regions and
+    /// undecodable pixel depths are all reported as errors before anything is captured, no
+    /// input makes it panic.
+    pub fn try_capture_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
         self.impl_monitor.capture_region(x, y, width, height)
     }
 
+    /// Capture a region of the monitor and map it through `curve`, so the output is in a
+    /// known colorspace even when the framebuffer isn't sRGB.
+    pub fn capture_region_with_curve(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        curve: &ToneCurve,
+    ) -> XCapResult<RgbaImage> {
+        let mut image = self.impl_monitor.capture_region(x, y, width, height)?;
+        curve.apply(&mut image);
+
+        Ok(image)
+    }
+
+    /// Capture a region of the monitor with the `(x, y, width, height)` rectangles of `mask`,
+    /// relative to the region, made fully transparent, e.g. to blank out notifications in a
+    /// screen share. Parts of rectangles reaching past the region are ignored.
+    ///
+    /// The mask is applied to the captured image rather than while decoding, as one slice
+    /// fill per masked row segment, so it works the same on every backend.
+    pub fn capture_region_masked(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        mask: &[(u32, u32, u32, u32)],
+    ) -> XCapResult<RgbaImage> {
+        let mut image = self.impl_monitor.capture_region(x, y, width, height)?;
+        clear_rects(&mut image, mask);
+
+        Ok(image)
+    }
+
+    /// Capture a region given in fractional pixels, e.g. scaled from device independent units.
+    /// The left and right edges, and the top and bottom ones, are snapped with `rounding`
+    /// rather than the width and height, so regions sharing an edge never overlap or leave a
+    /// gap. The result is clamped to the monitor, a region with nothing left is an error.
+    pub fn capture_region_f(
+        &self,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        rounding: Rounding,
+    ) -> XCapResult<RgbaImage> {
+        let (monitor_width, monitor_height) = self.size()?;
+        let (x, y, width, height) = snap_region(
+            (x, y, width, height),
+            rounding,
+            monitor_width,
+            monitor_height,
+        )
+        .ok_or_else(|| {
+            XCapError::InvalidCaptureRegion(format!(
+                "Region ({}, {}, {}, {}) does not cover any pixel of the monitor",
+                x, y, width, height
+            ))
+        })?;
+
+        self.impl_monitor.capture_region(x, y, width, height)
+    }
+
+    /// Same as [`Monitor::try_capture_region`], but a region reaching past the monitor's edge,
+    /// e.g. off by one after scaling coordinates, is clamped to the monitor and captured
+    /// again once. Returns the image with the `(x, y, width, height)` actually captured.
+    pub fn capture_region_clamped(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(RgbaImage, (u32, u32, u32, u32))> {
+        let err = match self.try_capture_region(x, y, width, height) {
+            Ok(image) => return Ok((image, (x, y, width, height))),
+            Err(err) => err,
+        };
+
+        let (monitor_width, monitor_height) = self.size()?;
+        match clamp_region((x, y, width, height), monitor_width, monitor_height) {
+            Some(region) if region != (x, y, width, height) => {
+                let (x, y, width, height) = region;
+                let image = self.try_capture_region(x, y, width, height)?;
+
+                Ok((image, region))
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Capture a region along with its timestamp, origin and the monitor it came from.
+    pub fn capture_region_frame(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<CaptureFrame> {
+        let monitor_name = self.name()?;
+
+        let captured_at = Instant::now();
+        let image = self.capture_region(x, y, width, height)?;
+
+        // The capture validated the region, the origin still can't be trusted to fit an i32
+        // for monitors at the edge of the coordinate range
+        let origin = |position: i32, offset: u32| {
+            i32::try_from(position as i64 + offset as i64)
+                .map_err(|_| XCapError::new("Region origin is out of range"))
+        };
+        let origin_x = origin(self.x()?, x)?;
+        let origin_y = origin(self.y()?, y)?;
+
+        Ok(CaptureFrame {
+            image,
+            captured_at,
+            x: origin_x,
+            y: origin_y,
+            monitor_name,
+        })
+    }
+
+    /// Capture a region and encode it straight into `writer`, without buffering the encoded
+    /// bytes. PNG is always available, JPEG requires the `image` feature.
+    pub fn capture_region_to_writer<W: Write>(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+        writer: W,
+    ) -> XCapResult<()> {
+        let image = self.capture_region(x, y, width, height)?;
+
+        match format {
+            ImageFormat::Png => image.write_with_encoder(PngEncoder::new(writer)),
+            // Encoded from the RGBA pixels directly, no intermediate RGB copy
+            #[cfg(feature = "image")]
+            ImageFormat::Jpeg => JpegEncoder::new(writer).encode_image(&image),
+            format => {
+                return Err(XCapError::new(format!(
+                    "Unsupported image format {:?}",
+                    format
+                )));
+            }
+        }
+        .map_err(XCapError::new)
+    }
+
+    /// Capture a rectangle in virtual desktop coordinates, which may span several monitors.
+    /// Each monitor contributes the part it overlaps; parts not covered by any monitor are
+    /// left transparent.
+    pub fn capture_virtual_region(
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        if (width as u64) * (height as u64) > isize::MAX as u64 / 4 {
+            return Err(XCapError::RegionTooLarge { width, height });
+        }
+
+        let region = (x as i64, y as i64, width as i64, height as i64);
+        let mut canvas = RgbaImage::new(width, height);
+        let mut covered = false;
+
+        for monitor in Monitor::all()? {
+            let bounds = (
+                monitor.x()? as i64,
+                monitor.y()? as i64,
+                monitor.width()? as i64,
+                monitor.height()? as i64,
+            );
+
+            let Some((left, top, right, bottom)) = intersect(region, bounds) else {
+                continue;
+            };
+
+            let image = monitor.capture_region(
+                (left - bounds.0) as u32,
+                (top - bounds.1) as u32,
+                (right - left) as u32,
+                (bottom - top) as u32,
+            )?;
+            imageops::replace(&mut canvas, &image, left - region.0, top - region.1);
+            covered = true;
+        }
+
+        if !covered {
+            return Err(XCapError::InvalidCaptureRegion(format!(
+                "Region ({}, {}, {}, {}) does not overlap any monitor",
+                x, y, width, height
+            )));
+        }
+
+        Ok(canvas)
+    }
+
     pub fn video_recorder(&self) -> XCapResult<(VideoRecorder, Receiver<Frame>)> {
         let (impl_video_recorder, sx) = self.impl_monitor.video_recorder()?;
 
@@ -99,8 +421,527 @@ impl Monitor {
     }
 }
 
+#[cfg(target_os = "linux")]
+impl Monitor {
+    /// Current pointer position in the same coordinates as [`Monitor::x`] and [`Monitor::y`].
+    /// On Wayland the X server only sees the pointer while it is over an X window.
+    pub fn cursor_position() -> XCapResult<(i32, i32)> {
+        ImplMonitor::cursor_position()
+    }
+
+    /// The monitor the pointer is on.
+    pub fn from_cursor() -> XCapResult<Monitor> {
+        let impl_monitor = ImplMonitor::from_cursor()?;
+
+        Ok(Monitor::new(impl_monitor))
+    }
+
+    /// The X server is reached over TCP, e.g. on another host or forwarded by `ssh -X`.
+    /// Every capture then moves the full image over the network without MIT-SHM, tools can
+    /// use this to warn or capture at a lower resolution.
+    pub fn is_remote_display() -> XCapResult<bool> {
+        ImplMonitor::is_remote_display()
+    }
+
+    /// Capture every monitor in one go, each image paired with its monitor. On X11 all
+    /// requests go out on one connection before the first reply is read, so the round
+    /// trips overlap instead of adding up.
+    pub fn capture_all() -> XCapResult<Vec<(Monitor, RgbaImage)>> {
+        let captures = ImplMonitor::capture_all()?
+            .into_iter()
+            .map(|(impl_monitor, rgba_image)| (Monitor::new(impl_monitor), rgba_image))
+            .collect();
+
+        Ok(captures)
+    }
+
+    /// The monitor driven by the connected RandR output `name`, e.g. `"DP-1"`. Output names
+    /// stay the same across reboots, unlike the position in [`Monitor::all`].
+    pub fn from_name(name: &str) -> XCapResult<Monitor> {
+        let impl_monitor = ImplMonitor::from_name(name)?;
+
+        Ok(Monitor::new(impl_monitor))
+    }
+
+    /// Capture the monitor on tokio's blocking thread pool, so the executor isn't stalled
+    /// while waiting for the X server.
+    #[cfg(feature = "tokio")]
+    pub async fn capture_image_async(&self) -> XCapResult<RgbaImage> {
+        let monitor = self.clone();
+
+        tokio::task::spawn_blocking(move || monitor.capture_image())
+            .await
+            .map_err(XCapError::new)?
+    }
+
+    /// Capture every monitor into one image covering their bounding box, monitors left of or
+    /// above the primary are handled by offsetting from the box origin. Gaps stay transparent.
+    pub fn capture_all_combined() -> XCapResult<RgbaImage> {
+        let mut bounds: Option<(i64, i64, i64, i64)> = None;
+        let mut captures = Vec::new();
+
+        for monitor in Monitor::all()? {
+            let x = monitor.x()? as i64;
+            let y = monitor.y()? as i64;
+            let right = x + monitor.width()? as i64;
+            let bottom = y + monitor.height()? as i64;
+
+            bounds = Some(match bounds {
+                Some((left, top, max_right, max_bottom)) => (
+                    left.min(x),
+                    top.min(y),
+                    max_right.max(right),
+                    max_bottom.max(bottom),
+                ),
+                None => (x, y, right, bottom),
+            });
+            captures.push((x, y, monitor.capture_image()?));
+        }
+
+        let (left, top, right, bottom) = bounds.ok_or(XCapError::new("Not found monitor"))?;
+        let width = u32::try_from(right - left).map_err(XCapError::new)?;
+        let height = u32::try_from(bottom - top).map_err(XCapError::new)?;
+        buffer_len(width, height, 4)?;
+
+        let mut canvas = RgbaImage::new(width, height);
+
+        for (x, y, image) in captures {
+            imageops::replace(&mut canvas, &image, x - left, y - top);
+        }
+
+        Ok(canvas)
+    }
+
+    /// Capture the entire X screen at its full size in one request, without going through the
+    /// monitors. Usually cheaper than [`Monitor::capture_all_combined`] on a single framebuffer.
+    pub fn capture_root() -> XCapResult<RgbaImage> {
+        ImplMonitor::capture_root()
+    }
+
+    /// Capture the root window of X screen `screen`, the `N` of a `:0.N` display, on servers
+    /// running several screens side by side rather than several monitors on one screen.
+    pub fn capture_screen(screen: usize) -> XCapResult<RgbaImage> {
+        ImplMonitor::capture_screen(screen)
+    }
+
+    /// Index of the X screen the monitor is on, 0 unless the server runs several screens.
+    /// [`Monitor::x`] and [`Monitor::y`] are relative to that screen's root window.
+    pub fn screen(&self) -> XCapResult<usize> {
+        self.impl_monitor.screen()
+    }
+
+    /// RGBA color of the pixel at monitor relative `(x, y)`, without capturing an image.
+    pub fn pixel_color(&self, x: u32, y: u32) -> XCapResult<(u8, u8, u8, u8)> {
+        self.impl_monitor.pixel_color(x, y)
+    }
+
+    /// Physical pixel density from the monitor's resolution and the size RandR reports for it,
+    /// independent of the `Xft.dpi` setting behind [`Monitor::scale_factor`]. Dividing by 96
+    /// gives the scale a HiDPI aware desktop would likely pick.
+    pub fn dpi(&self) -> XCapResult<f32> {
+        self.impl_monitor.dpi()
+    }
+
+    /// Refresh rate of the output's current RandR mode in Hz. Unlike [`Monitor::frequency`]
+    /// this fails instead of returning 0 when the mode can't be read.
+    pub fn refresh_rate(&self) -> XCapResult<f32> {
+        self.impl_monitor.refresh_rate()
+    }
+
+    /// Same as [`Monitor::frames`] paced at [`Monitor::refresh_rate`], capturing any faster
+    /// would only duplicate frames.
+    pub fn frames_at_refresh_rate(&self) -> XCapResult<FrameStream> {
+        self.impl_monitor.frames_at_refresh_rate()
+    }
+
+    /// Capture the whole monitor repeatedly, yielding at most `fps` frames per second.
+    /// The iterator sleeps between frames and keeps its X11 connection until dropped.
+    pub fn frames(&self, fps: u32) -> XCapResult<FrameStream> {
+        self.impl_monitor.frames(fps)
+    }
+
+    /// Same as [`Monitor::capture_image`] with the output's RandR rotation and reflection
+    /// applied, giving the image in the panel's scanout orientation. [`Monitor::capture_image`]
+    /// returns what the user sees, RandR only transforms the root window at scanout. X11 only.
+    pub fn capture_image_scanout(&self) -> XCapResult<RgbaImage> {
+        self.impl_monitor.capture_image_scanout()
+    }
+
+    /// Capture the monitor over a connection the application already holds, instead of the
+    /// one xcap keeps internally. X11 only, the connection's crate is re-exported as
+    /// [`xcap::xcb`](crate::xcb).
+    pub fn capture_image_with_connection(&self, conn: &xcb::Connection) -> XCapResult<RgbaImage> {
+        self.impl_monitor.capture_image_with_connection(conn)
+    }
+
+    /// Capture the monitor without decoding, returning the X server's `ZPixmap` data as is.
+    /// Not supported on Wayland.
+    pub fn capture_raw(&self) -> XCapResult<RawImage> {
+        self.impl_monitor.capture_raw(None)
+    }
+
+    /// Same as [`Monitor::capture_raw`] reading only the bit planes set in `plane_mask`, e.g.
+    /// [`RawImage::red_mask`] of an earlier capture for the red channel alone. `None` reads
+    /// every plane, like `u32::MAX`. The returned pixels keep their full size, the server
+    /// fills the bits of masked out planes with 0.
+    pub fn capture_raw_with_plane_mask(&self, plane_mask: Option<u32>) -> XCapResult<RawImage> {
+        self.impl_monitor.capture_raw(plane_mask)
+    }
+
+    /// Capture a region of the monitor into `buf` as RGBA, reusing its allocation across calls.
+    /// Returns the captured `(width, height)`.
+    pub fn capture_region_into(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut Vec<u8>,
+    ) -> XCapResult<(u32, u32)> {
+        self.impl_monitor
+            .capture_region_into(x, y, width, height, buf)
+    }
+
+    /// Same as [`Monitor::capture_region_into`] writing into `image`, whose pixel buffer is
+    /// reused in place when it already is `width` x `height` and resized otherwise. After a
+    /// failed capture the image keeps a valid size, its pixels are unspecified.
+    pub fn capture_region_reuse(
+        &self,
+        image: &mut RgbaImage,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<()> {
+        let dimensions = image.dimensions();
+        let mut buf = mem::take(image).into_raw();
+
+        let result = self
+            .impl_monitor
+            .capture_region_into(x, y, width, height, &mut buf);
+        let (width, height) = result.as_ref().map_or(dimensions, |&dimensions| dimensions);
+
+        *image = RgbaImage::from_raw(width, height, buf).unwrap_or_default();
+
+        result.map(|_| ())
+    }
+
+    /// Same as [`Monitor::capture_region_into`] with 3 bytes per pixel in `R, G, B` order.
+    pub fn capture_region_rgb_into(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut Vec<u8>,
+    ) -> XCapResult<(u32, u32)> {
+        self.impl_monitor
+            .capture_region_rgb_into(x, y, width, height, buf)
+    }
+
+    /// Capture a region of the monitor as tightly packed rows of 4 bytes per pixel, in
+    /// `B, G, R, A` memory order, for GPU texture uploads and video encoders. On little endian
+    /// 24/32 bit displays this is a plain copy of the server's data.
+    pub fn capture_region_bgra(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<Vec<u8>> {
+        self.impl_monitor.capture_region_bgra(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor as tightly packed rows of 3 bytes per pixel, in
+    /// `B, G, R` memory order, so the buffer can be wrapped by an OpenCV `CV_8UC3` `Mat` of
+    /// `height` rows and `width` columns with a step of `width * 3` bytes. On little endian
+    /// 24/32 bit displays this is a copy of the server's data without the fourth byte.
+    pub fn capture_region_bgr(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<Vec<u8>> {
+        self.impl_monitor.capture_region_bgr(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor as a [`DynamicImage`], `ImageRgba8` when the source
+    /// has an alpha channel and `ImageRgb8` otherwise.
+    pub fn capture_region_dynamic(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<DynamicImage> {
+        self.impl_monitor
+            .capture_region_dynamic(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor downscaled by `divisor`, sampling every `divisor`th
+    /// pixel while decoding so the full resolution image is never built.
+    /// The result is `width / divisor` x `height / divisor`.
+    pub fn capture_region_scaled(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        divisor: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.impl_monitor
+            .capture_region_scaled(x, y, width, height, divisor)
+    }
+
+    /// Capture a region of the monitor with the mouse pointer composited on top, using XFixes.
+    /// Not supported on Wayland.
+    pub fn capture_region_with_cursor(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.impl_monitor
+            .capture_region_with_cursor(x, y, width, height)
+    }
+
+    /// Capture a region straight to NV12 for video encoders, the Y and interleaved UV planes
+    /// are computed from the source pixels in one pass. `matrix` selects BT.601 or BT.709.
+    pub fn capture_region_nv12(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        matrix: YuvMatrix,
+    ) -> XCapResult<Nv12Image> {
+        self.impl_monitor
+            .capture_region_nv12(x, y, width, height, matrix)
+    }
+
+    /// Capture several `(x, y, width, height)` regions at once, in the same order. On X11
+    /// all requests are sent before the first reply is read, so the round trips overlap
+    /// instead of adding up. Every region is validated before anything is captured.
+    pub fn capture_regions(&self, regions: &[(u32, u32, u32, u32)]) -> XCapResult<Vec<RgbaImage>> {
+        self.impl_monitor.capture_regions(regions)
+    }
+
+    /// Same as [`Monitor::capture_region`] also reporting how long connecting, transferring
+    /// and decoding took, so each can be measured on its own. Not supported on Wayland.
+    pub fn capture_region_timed(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(RgbaImage, CaptureTimings)> {
+        self.impl_monitor.capture_region_timed(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor keeping the server's reply undecoded, so
+    /// [`ImageReply::bgra`] can borrow the pixels without copying them for GPU uploads.
+    /// Not supported on Wayland.
+    pub fn capture_region_reply(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<ImageReply> {
+        self.impl_monitor.capture_region_reply(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor and decode it lazily one RGBA row at a time, for
+    /// streaming consumers that shouldn't hold a second full size copy. Not supported on
+    /// Wayland.
+    pub fn capture_region_rows(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<ImageRows> {
+        self.capture_region_reply(x, y, width, height)?.into_rows()
+    }
+
+    /// Capture a region of the monitor as `f32` RGBA scaled to `0.0..=1.0` while decoding, for
+    /// machine learning input. With `standardization` the color channels are also shifted
+    /// by their mean and divided by their standard deviation in the same pass.
+    pub fn capture_region_f32(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        standardization: Option<Standardization>,
+    ) -> XCapResult<Rgba32FImage> {
+        self.impl_monitor
+            .capture_region_f32(x, y, width, height, standardization)
+    }
+
+    /// Capture a region of the monitor with R, G, B and A each in its own tightly packed
+    /// plane, written while decoding so no deinterleave pass is needed afterwards.
+    pub fn capture_region_planar(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<PlanarImage> {
+        self.impl_monitor.capture_region_planar(x, y, width, height)
+    }
+
+    /// Capture a region of the monitor straight into the file at `path`, created or
+    /// truncated to the size of the raw RGBA pixels and memory mapped while decoding, so even
+    /// enormous regions don't need a buffer of their size in memory. The file holds bare
+    /// rows without a header, the returned width, height and color type describe them.
+    /// Not supported on Wayland.
+    pub fn capture_region_to_mmap<P: AsRef<Path>>(
+        &self,
+        path: P,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<(u32, u32, ColorType)> {
+        self.impl_monitor
+            .capture_region_to_mmap(path.as_ref(), x, y, width, height)
+    }
+
+    /// Capture a region of the monitor as 8 bits luma, computed directly from the source pixels.
+    pub fn capture_region_gray(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<GrayImage> {
+        self.impl_monitor.capture_region_gray(x, y, width, height)
+    }
+
+    /// Same as [`Monitor::capture_region`] with every color channel multiplied by `alpha / 255`
+    /// while decoding, the layout Cairo and Skia expect when compositing. Sources without an
+    /// alpha channel are opaque, so their pixels come back unchanged.
+    pub fn capture_region_premultiplied(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> XCapResult<RgbaImage> {
+        self.impl_monitor
+            .capture_region_premultiplied(x, y, width, height)
+    }
+}
+
+/// Validate a monitor relative region against the monitor's geometry. Every platform's region
+/// capture goes through this, sums are widened so no input can overflow.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn validate_region(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    monitor_x: i32,
+    monitor_y: i32,
+    monitor_width: u32,
+    monitor_height: u32,
+) -> XCapResult<()> {
+    if width == 0 || height == 0 {
+        return Err(XCapError::InvalidRegion { width, height });
+    }
+
+    if x as u64 + width as u64 > monitor_width as u64
+        || y as u64 + height as u64 > monitor_height as u64
+    {
+        return Err(XCapError::InvalidCaptureRegion(format!(
+            "Region ({}, {}, {}, {}) is outside monitor bounds ({}, {}, {}, {})",
+            x, y, width, height, monitor_x, monitor_y, monitor_width, monitor_height
+        )));
+    }
+
+    if (width as u64) * (height as u64) > isize::MAX as u64 / 4 {
+        return Err(XCapError::RegionTooLarge { width, height });
+    }
+
+    Ok(())
+}
+
+/// Part of a monitor relative `(x, y, width, height)` region inside a `monitor_width` x
+/// `monitor_height` monitor, `None` when nothing of it is.
+fn clamp_region(
+    (x, y, width, height): (u32, u32, u32, u32),
+    monitor_width: u32,
+    monitor_height: u32,
+) -> Option<(u32, u32, u32, u32)> {
+    let width = width.min(monitor_width.checked_sub(x)?);
+    let height = height.min(monitor_height.checked_sub(y)?);
+
+    (width > 0 && height > 0).then_some((x, y, width, height))
+}
+
+/// Pixels covered by a fractional `(x, y, width, height)` region once its edges are snapped
+/// with `rounding` and clamped to a `monitor_width` x `monitor_height` monitor, `None` when
+/// nothing of it is left or a coordinate isn't finite.
+fn snap_region(
+    (x, y, width, height): (f64, f64, f64, f64),
+    rounding: Rounding,
+    monitor_width: u32,
+    monitor_height: u32,
+) -> Option<(u32, u32, u32, u32)> {
+    let snap = |start: f64, len: f64, max: u32| {
+        if !start.is_finite() || !len.is_finite() {
+            return None;
+        }
+
+        let start_edge = rounding.apply(start).clamp(0.0, max as f64);
+        let end_edge = rounding.apply(start + len).clamp(0.0, max as f64);
+
+        (end_edge > start_edge).then_some((start_edge as u32, (end_edge - start_edge) as u32))
+    };
+
+    let (x, width) = snap(x, width, monitor_width)?;
+    let (y, height) = snap(y, height, monitor_height)?;
+
+    Some((x, y, width, height))
+}
+
+/// Set every pixel of `image` inside one of the `rects` to transparent black.
+fn clear_rects(image: &mut RgbaImage, rects: &[(u32, u32, u32, u32)]) {
+    let (image_width, image_height) = image.dimensions();
+
+    for &rect in rects {
+        let Some((x, y, width, height)) = clamp_region(rect, image_width, image_height) else {
+            continue;
+        };
+
+        let (start, end) = (x as usize * 4, (x + width) as usize * 4);
+        for row in image
+            .chunks_exact_mut(image_width as usize * 4)
+            .skip(y as usize)
+            .take(height as usize)
+        {
+            row[start..end].fill(0);
+        }
+    }
+}
+
+/// Overlap of two `(x, y, width, height)` rectangles as `(left, top, right, bottom)`.
+fn intersect(a: (i64, i64, i64, i64), b: (i64, i64, i64, i64)) -> Option<(i64, i64, i64, i64)> {
+    let left = a.0.max(b.0);
+    let top = a.1.max(b.1);
+    let right = (a.0 + a.2).min(b.0 + b.2);
+    let bottom = (a.1 + a.3).min(b.1 + b.3);
+
+    (left < right && top < bottom).then_some((left, top, right, bottom))
+}
+
 #[cfg(test)]
 mod tests {
+    use image::Rgba;
+
     use crate::XCapError;
 
     use super::*;
@@ -123,4 +964,108 @@ mod tests {
             _ => panic!("Expected InvalidCaptureRegion error"),
         }
     }
+
+    #[test]
+    fn test_validate_region() {
+        assert!(validate_region(0, 0, 1920, 1080, 0, 0, 1920, 1080).is_ok());
+        assert!(matches!(
+            validate_region(0, 0, 0, 10, 0, 0, 1920, 1080),
+            Err(XCapError::InvalidRegion {
+                width: 0,
+                height: 10
+            })
+        ));
+        assert!(matches!(
+            validate_region(u32::MAX, u32::MAX, 10, 10, 0, 0, 1920, 1080),
+            Err(XCapError::InvalidCaptureRegion(_))
+        ));
+        assert!(matches!(
+            validate_region(0, 0, u32::MAX, u32::MAX, 0, 0, u32::MAX, u32::MAX),
+            Err(XCapError::RegionTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_intersect() {
+        let left_monitor = (0, 0, 1920, 1080);
+        let right_monitor = (1920, 0, 1280, 1024);
+        let region = (1800, 1000, 200, 100);
+
+        assert_eq!(
+            intersect(region, left_monitor),
+            Some((1800, 1000, 1920, 1080))
+        );
+        assert_eq!(
+            intersect(region, right_monitor),
+            Some((1920, 1000, 2000, 1024))
+        );
+        assert_eq!(intersect((3300, 0, 10, 10), right_monitor), None);
+        assert_eq!(intersect((1920, 0, 10, 10), left_monitor), None);
+    }
+
+    #[test]
+    fn test_clear_rects() {
+        let mut image = RgbaImage::from_pixel(4, 3, Rgba([1, 2, 3, 255]));
+        clear_rects(&mut image, &[(1, 1, 2, 1), (3, 2, 10, 10), (4, 0, 1, 1)]);
+
+        let cleared: Vec<_> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0[3] == 0)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(cleared, [(1, 1), (2, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn test_snap_region() {
+        let region = (10.4, 20.5, 99.3, 0.6);
+        assert_eq!(
+            snap_region(region, Rounding::Floor, 1920, 1080),
+            Some((10, 20, 99, 1))
+        );
+        // 20.5 and 21.1 both round to 21, nothing is left of the height
+        assert_eq!(snap_region(region, Rounding::Round, 1920, 1080), None);
+        assert_eq!(
+            snap_region((10.4, 20.5, 99.3, 1.2), Rounding::Round, 1920, 1080),
+            Some((10, 21, 100, 1))
+        );
+        assert_eq!(
+            snap_region(region, Rounding::Ceil, 1920, 1080),
+            Some((11, 21, 99, 1))
+        );
+
+        // Neighbours sharing a fractional edge tile without overlap
+        let (left_x, _, left_width, _) =
+            snap_region((0.0, 0.0, 33.5, 1.0), Rounding::Round, 1920, 1080).unwrap();
+        let (right_x, ..) =
+            snap_region((33.5, 0.0, 33.5, 1.0), Rounding::Round, 1920, 1080).unwrap();
+        assert_eq!(left_x + left_width, right_x);
+
+        assert_eq!(
+            snap_region((-5.5, 1070.2, 100.0, 20.0), Rounding::Round, 1920, 1080),
+            Some((0, 1070, 95, 10))
+        );
+        assert_eq!(
+            snap_region((1920.0, 0.0, 10.0, 10.0), Rounding::Round, 1920, 1080),
+            None
+        );
+        assert_eq!(
+            snap_region((f64::NAN, 0.0, 10.0, 10.0), Rounding::Round, 1920, 1080),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clamp_region() {
+        assert_eq!(
+            clamp_region((0, 0, 1921, 1081), 1920, 1080),
+            Some((0, 0, 1920, 1080))
+        );
+        assert_eq!(
+            clamp_region((100, 50, 10, 10), 1920, 1080),
+            Some((100, 50, 10, 10))
+        );
+        assert_eq!(clamp_region((1920, 0, 10, 10), 1920, 1080), None);
+        assert_eq!(clamp_region((0, 0, 0, 10), 1920, 1080), None);
+    }
 }
@@ -0,0 +1,45 @@
+use image::{RgbImage, RgbaImage};
+
+/// Drop the alpha byte of every pixel of an RGBA buffer, compacting it to `R, G, B` in place.
+/// The buffer is truncated to 3 bytes per pixel and keeps its allocation, so it can be
+/// handed back to e.g. `Monitor::capture_region_into` on the next capture.
+pub fn rgba_to_rgb_in_place(buf: &mut Vec<u8>) {
+    let pixels = buf.len() / 4;
+
+    // Each destination ends before its source starts, earlier pixels are never overwritten
+    for pixel in 0..pixels {
+        buf.copy_within(pixel * 4..pixel * 4 + 3, pixel * 3);
+    }
+
+    buf.truncate(pixels * 3);
+}
+
+/// Same as [`rgba_to_rgb_in_place`] for a whole image, reusing its pixel buffer.
+pub fn rgba_image_into_rgb(image: RgbaImage) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let mut buf = image.into_raw();
+    rgba_to_rgb_in_place(&mut buf);
+
+    RgbImage::from_raw(width, height, buf).expect("compacted buffer matches the dimensions")
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn test_rgba_to_rgb_in_place() {
+        let mut buf = vec![1, 2, 3, 255, 4, 5, 6, 128, 7, 8, 9, 0];
+        let capacity = buf.capacity();
+
+        rgba_to_rgb_in_place(&mut buf);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(buf.capacity(), capacity);
+
+        let rgb = rgba_image_into_rgb(RgbaImage::from_pixel(2, 3, Rgba([10, 20, 30, 40])));
+        assert_eq!(rgb.dimensions(), (2, 3));
+        assert!(rgb.pixels().all(|pixel| *pixel == Rgb([10, 20, 30])));
+    }
+}
@@ -0,0 +1,82 @@
+use image::RgbaImage;
+
+/// Per channel lookup tables mapping captured framebuffer values to output values, e.g.
+/// to bring a capture from a monitor with a non-sRGB transfer function into sRGB.
+///
+/// Captures are raw by default, the curve only applies through
+/// [`crate::Monitor::capture_region_with_curve`] or [`ToneCurve::apply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToneCurve {
+    lut: [[u8; 256]; 3],
+}
+
+/// sRGB encoding of a linear intensity in `0.0..=1.0`.
+fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneCurve {
+    /// Framebuffer values encoded with a pure power `gamma` converted to sRGB, `1.0` for a
+    /// linear framebuffer.
+    pub fn from_gamma(gamma: f32) -> ToneCurve {
+        let mut table = [0; 256];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let linear = (value as f32 / 255.0).powf(gamma);
+            *entry = (linear_to_srgb(linear) * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+
+        ToneCurve { lut: [table; 3] }
+    }
+
+    /// Tables supplied by the caller, e.g. derived from the monitor's ICC profile.
+    pub fn from_lut(red: [u8; 256], green: [u8; 256], blue: [u8; 256]) -> ToneCurve {
+        ToneCurve {
+            lut: [red, green, blue],
+        }
+    }
+
+    /// Map every pixel of `image` through the tables, alpha is left as is.
+    pub fn apply(&self, image: &mut RgbaImage) {
+        let [red, green, blue] = &self.lut;
+
+        for [r, g, b, _] in image.as_chunks_mut::<4>().0 {
+            *r = red[*r as usize];
+            *g = green[*g as usize];
+            *b = blue[*b as usize];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_tone_curve() {
+        let linear = ToneCurve::from_gamma(1.0);
+        assert_eq!(linear.lut[0][0], 0);
+        assert_eq!(linear.lut[0][255], 255);
+        // 50% linear intensity is about 73% in sRGB
+        assert_eq!(linear.lut[1][128], 188);
+
+        // sRGB is close to gamma 2.2, mid tones barely move
+        let gamma = ToneCurve::from_gamma(2.2);
+        assert!(gamma.lut[2][128].abs_diff(128) <= 2);
+
+        let mut inverted = [0; 256];
+        for (value, entry) in inverted.iter_mut().enumerate() {
+            *entry = 255 - value as u8;
+        }
+        let curve = ToneCurve::from_lut(inverted, [0; 256], [255; 256]);
+
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([10, 20, 30, 40]));
+        curve.apply(&mut image);
+        assert_eq!(image.get_pixel(1, 0), &Rgba([245, 0, 255, 40]));
+    }
+}
@@ -0,0 +1,51 @@
+use image::{RgbaImage, imageops};
+
+/// Remove the fully transparent rows and columns around `image`, e.g. the shadow margin of
+/// a window captured with alpha. Returns the trimmed image and the position of its top left
+/// corner in the original, a wholly transparent image trims down to 0x0 at `(0, 0)`.
+pub fn trim_transparent(image: &RgbaImage) -> (RgbaImage, (u32, u32)) {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+
+    let Some((left, top, right, bottom)) = bounds else {
+        return (RgbaImage::new(0, 0), (0, 0));
+    };
+
+    let trimmed = imageops::crop_imm(image, left, top, right - left + 1, bottom - top + 1);
+
+    (trimmed.to_image(), (left, top))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_trim_transparent() {
+        let mut image = RgbaImage::new(4, 3);
+        image.put_pixel(1, 1, Rgba([1, 2, 3, 255]));
+        image.put_pixel(2, 1, Rgba([0, 0, 0, 1]));
+
+        let (trimmed, offset) = trim_transparent(&image);
+        assert_eq!(offset, (1, 1));
+        assert_eq!(trimmed.dimensions(), (2, 1));
+        assert_eq!(trimmed.get_pixel(0, 0), &Rgba([1, 2, 3, 255]));
+
+        let (trimmed, offset) = trim_transparent(&RgbaImage::new(2, 2));
+        assert_eq!((trimmed.dimensions(), offset), ((0, 0), (0, 0)));
+    }
+}
@@ -1,5 +1,9 @@
+#[cfg(target_os = "linux")]
+use image::GrayImage;
 use image::RgbaImage;
 
+#[cfg(target_os = "linux")]
+use crate::RawImage;
 use crate::{Monitor, error::XCapResult, platform::impl_window::ImplWindow};
 
 #[derive(Debug, Clone)]
@@ -34,11 +38,11 @@ impl Window {
     pub fn pid(&self) -> XCapResult<u32> {
         self.impl_window.pid()
     }
-    /// The window app name
+    /// The window app name, on Linux the class part of `WM_CLASS`
     pub fn app_name(&self) -> XCapResult<String> {
         self.impl_window.app_name()
     }
-    /// The window title
+    /// The window title, on Linux `_NET_WM_NAME` falling back to `WM_NAME`
     pub fn title(&self) -> XCapResult<String> {
         self.impl_window.title()
     }
@@ -81,7 +85,111 @@ impl Window {
 }
 
 impl Window {
+    /// Capture the window as 8 bits per channel RGBA, whatever the depth of its visual.
     pub fn capture_image(&self) -> XCapResult<RgbaImage> {
         self.impl_window.capture_image()
     }
 }
+
+#[cfg(target_os = "linux")]
+impl Window {
+    /// Wrap a known X11 window id, e.g. one obtained from another tool, erroring when no
+    /// such window exists.
+    pub fn from_xid(id: u32) -> XCapResult<Window> {
+        let impl_window = ImplWindow::from_xid(id)?;
+
+        Ok(Window::new(impl_window))
+    }
+
+    /// List all windows like [`Window::all`] together with a thumbnail of each, downscaled by
+    /// an integer divisor to fit in `max_dim` x `max_dim`. All captures share one connection,
+    /// unmapped, minimized and empty windows are skipped, as are windows too thin to keep a
+    /// pixel once scaled.
+    pub fn all_with_thumbnails(max_dim: u32) -> XCapResult<Vec<(Window, RgbaImage)>> {
+        let thumbnails = ImplWindow::all_with_thumbnails(max_dim)?
+            .into_iter()
+            .map(|(impl_window, thumbnail)| (Window::new(impl_window), thumbnail))
+            .collect();
+
+        Ok(thumbnails)
+    }
+
+    /// The window that has the input focus, read from the window manager's
+    /// `_NET_ACTIVE_WINDOW`. Fails with [`crate::XCapError::NoActiveWindow`] when none is set.
+    pub fn active() -> XCapResult<Window> {
+        let impl_window = ImplWindow::active()?;
+
+        Ok(Window::new(impl_window))
+    }
+
+    /// The window is mapped and not minimized, so a capture shows its contents instead of
+    /// black or stale pixels. Reads the map state, `_NET_WM_STATE` and ICCCM `WM_STATE`.
+    pub fn is_visible(&self) -> XCapResult<bool> {
+        self.impl_window.is_visible()
+    }
+
+    /// Same as [`Window::capture_image`], but fails with [`crate::XCapError::WindowNotVisible`]
+    /// without capturing anything when [`Window::is_visible`] is false.
+    pub fn capture_image_if_visible(&self) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_image_if_visible()
+    }
+
+    /// Same as [`Window::capture_image`], also telling whether the alpha channel is
+    /// significant, i.e. some pixel is not fully opaque. Callers can then store the image as
+    /// RGB without scanning it again. Always false for windows without an alpha visual.
+    pub fn capture_image_with_alpha_info(&self) -> XCapResult<(RgbaImage, bool)> {
+        self.impl_window.capture_image_with_alpha_info()
+    }
+
+    /// Raise the window to the top before capturing it, so windows covering it don't show up
+    /// in the capture on X11 setups without Composite. The window manager has 500ms to handle
+    /// the request, the capture is made either way; docks, panels and always-on-top windows
+    /// keep covering it. With `restore` it is put back below the window that covered it; a
+    /// window manager may refuse that for its frames, restoring is best effort and never
+    /// fails the capture. Not supported on Wayland.
+    pub fn capture_image_raised(&self, restore: bool) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_image_raised(restore)
+    }
+
+    /// Capture the window over a connection the application already holds, instead of the
+    /// one xcap keeps internally. The connection's crate is re-exported as
+    /// [`xcap::xcb`](crate::xcb). On Wayland this reads Xwayland windows through the X
+    /// connection, native Wayland windows aren't listed as X windows to begin with.
+    pub fn capture_image_with_connection(&self, conn: &xcb::Connection) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_image_with_connection(conn)
+    }
+
+    /// Capture a sub-rectangle of the window, relative to its top left corner. On Wayland the
+    /// window is captured like [`Window::capture_image`] does and the region cropped out of
+    /// that image, so it is relative to the compositor's capture and checked against its size.
+    pub fn capture_region(&self, x: u32, y: u32, width: u32, height: u32) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_region(x, y, width, height)
+    }
+
+    /// Capture the window as 8 bits luma, computed directly from the source pixels.
+    pub fn capture_image_gray(&self) -> XCapResult<GrayImage> {
+        self.impl_window.capture_image_gray()
+    }
+
+    /// Capture the window from its Composite backing pixmap, so parts covered by other
+    /// windows come out complete. Falls back to [`Window::capture_image`]'s direct capture
+    /// when the Composite extension isn't available.
+    ///
+    /// The window stays redirected between captures. Without a compositing manager the
+    /// covered parts only fill in once the window repainted, so the first capture can show
+    /// stale contents there.
+    pub fn capture_image_composite(&self) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_image_composite()
+    }
+
+    /// Capture the window together with the title bar and borders of its window manager
+    /// frame, as shown on screen. [`Window::capture_image`] only captures the client area.
+    pub fn capture_image_with_frame(&self) -> XCapResult<RgbaImage> {
+        self.impl_window.capture_image_with_frame()
+    }
+
+    /// Capture the window without decoding, returning the X server's `ZPixmap` data as is.
+    pub fn capture_raw(&self) -> XCapResult<RawImage> {
+        self.impl_window.capture_raw()
+    }
+}
@@ -27,7 +27,13 @@ fn to_rgba_image(
     width: i32,
     height: i32,
 ) -> XCapResult<RgbaImage> {
-    let buffer_size = width * height * 4;
+    let buffer_size = width
+        .checked_mul(height)
+        .and_then(|size| size.checked_mul(4))
+        .ok_or(XCapError::RegionTooLarge {
+            width: width as u32,
+            height: height as u32,
+        })?;
     let mut bitmap_info = BITMAPINFO {
         bmiHeader: BITMAPINFOHEADER {
             biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
@@ -21,6 +21,7 @@ use windows::{
 
 use crate::{
     error::{XCapError, XCapResult},
+    monitor::validate_region,
     video_recorder::Frame,
 };
 
@@ -286,16 +287,16 @@ impl ImplMonitor {
         let monitor_width = self.width()?;
         let monitor_height = self.height()?;
 
-        if width > monitor_width
-            || height > monitor_height
-            || x as u32 + width > monitor_width
-            || y as u32 + height > monitor_height
-        {
-            return Err(XCapError::InvalidCaptureRegion(format!(
-                "Region ({}, {}, {}, {}) is outside monitor bounds ({}, {}, {}, {})",
-                x, y, width, height, monitor_x, monitor_y, monitor_width, monitor_height
-            )));
-        }
+        validate_region(
+            x,
+            y,
+            width,
+            height,
+            monitor_x,
+            monitor_y,
+            monitor_width,
+            monitor_height,
+        )?;
 
         // Calculate absolute coordinates
         let abs_x = monitor_x + x as i32;
@@ -88,7 +88,7 @@ pub(super) fn bgra_to_rgba_image(
     buffer: Vec<u8>,
 ) -> XCapResult<RgbaImage> {
     RgbaImage::from_raw(width, height, bgra_to_rgba(buffer))
-        .ok_or_else(|| XCapError::new("RgbaImage::from_raw failed"))
+        .ok_or_else(|| XCapError::ImageConversion("RgbaImage::from_raw failed".to_string()))
 }
 
 // 定义 GetProcessDpiAwareness 函数的类型